@@ -0,0 +1,98 @@
+use super::errors::ApiError;
+use super::helpers;
+use super::schema;
+
+use actix_web::HttpResponse;
+use arrow::array::{ArrayRef, Float64Array, StringArray, TimestampMillisecondArray};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+use arrow::ipc::writer::StreamWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// gridded timeseries as a single arrow table /////////////////////////////////
+
+// the same rows as the csv: one per (id, longitude, latitude, level, timestamp), one column per variable in data_info.0.
+// missing values are nulls, and each variable's data_info rides along as field metadata.
+// `timeseries` is used for results that were never sliced and so don't carry their own.
+pub fn timeseries_batch<T: schema::IsTimeseries>(mut results: Vec<T>, data_info: &schema::DataInfo, timeseries: &[String]) -> Result<RecordBatch, ApiError> {
+    let mut ids = Vec::new();
+    let mut longitudes = Vec::new();
+    let mut latitudes = Vec::new();
+    let mut levels = Vec::new();
+    let mut timestamps = Vec::new();
+    let mut values: Vec<Vec<Option<f64>>> = vec![Vec::new(); data_info.0.len()];
+
+    for result in &mut results {
+        let (id, longitude, latitude, level) = (result._id(), result.longitude(), result.latitude(), result.level());
+        let record_times = result.timeseries().map(|t| t.clone()).unwrap_or_else(|| timeseries.to_vec());
+        let data = result.data();
+
+        for (i, time) in record_times.iter().enumerate() {
+            ids.push(id.clone());
+            longitudes.push(longitude);
+            latitudes.push(latitude);
+            levels.push(level);
+            timestamps.push(helpers::string2bsondate(time).map(|t| t.timestamp_millis()));
+            for (v, column) in values.iter_mut().enumerate() {
+                column.push(data.get(v).and_then(|variable| variable.get(i)).copied().filter(|x| !x.is_nan()));
+            }
+        }
+    }
+
+    let mut fields = vec![
+        Field::new("_id", DataType::Utf8, false),
+        Field::new("longitude", DataType::Float64, false),
+        Field::new("latitude", DataType::Float64, false),
+        Field::new("level", DataType::Float64, false),
+        Field::new("timestamp", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), true),
+    ];
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from(ids)),
+        Arc::new(Float64Array::from(longitudes)),
+        Arc::new(Float64Array::from(latitudes)),
+        Arc::new(Float64Array::from(levels)),
+        Arc::new(TimestampMillisecondArray::from(timestamps).with_timezone("UTC")),
+    ];
+
+    for (v, column) in values.into_iter().enumerate() {
+        let metadata: HashMap<String, String> = data_info.1.iter().enumerate()
+            .filter_map(|(a, attribute)| Some((attribute.clone(), data_info.2.get(v)?.get(a)?.clone())))
+            .collect();
+        fields.push(Field::new(&data_info.0[v], DataType::Float64, true).with_metadata(metadata));
+        columns.push(Arc::new(Float64Array::from(column)));
+    }
+
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)?)
+}
+
+#[tracing::instrument(name = "serialize", skip_all, fields(format = "arrow"))]
+pub fn timeseries_arrow<T: schema::IsTimeseries>(results: Vec<T>, data_info: &schema::DataInfo, timeseries: &[String]) -> Result<HttpResponse, ApiError> {
+    let batch = timeseries_batch(results, data_info, timeseries)?;
+
+    let mut writer = StreamWriter::try_new(Vec::new(), &batch.schema())?;
+    writer.write(&batch)?;
+    let bytes = writer.into_inner()?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.apache.arrow.stream")
+        .body(bytes))
+}
+
+#[tracing::instrument(name = "serialize", skip_all, fields(format = "parquet"))]
+pub fn timeseries_parquet<T: schema::IsTimeseries>(dataset: &str, results: Vec<T>, data_info: &schema::DataInfo, timeseries: &[String]) -> Result<HttpResponse, ApiError> {
+    let batch = timeseries_batch(results, data_info, timeseries)?;
+
+    let properties = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(Vec::new(), batch.schema(), Some(properties))?;
+    writer.write(&batch)?;
+    let bytes = writer.into_inner()?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.apache.parquet")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.parquet\"", dataset)))
+        .body(bytes))
+}
@@ -0,0 +1,235 @@
+use super::datasets::{self, DatasetSpec};
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+
+// where the configuration file is looked for, unless ARGOVIS_CONFIG names another
+pub const CONFIG_FILE: &str = "config.toml";
+
+// environment variables starting with this override the field they name, like ARGOVIS_PAGE_SIZE=500.
+// those naming no field are ignored, since the prefix is shared with others, like the ARGOVIS_API_SERVICE_HOST
+// kubernetes sets for a service named argovis-api
+pub const ENV_PREFIX: &str = "ARGOVIS_";
+
+// settings shared by every handler; every field has a default, so a config file only needs what it changes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // server
+    pub bind_address: String,
+    pub workers: Option<usize>,                // defaults to one per CPU
+    pub client_request_timeout_secs: u64,      // for a client to send its request head
+    pub shutdown_timeout_secs: u64,
+
+    // mongodb
+    pub mongodb_uri: Option<String>,           // MONGODB_URI is also honored
+    pub database: String,
+    pub mongo_min_pool_size: Option<u32>,
+    pub mongo_max_pool_size: Option<u32>,
+    pub mongo_connect_timeout_secs: u64,
+    pub mongo_server_selection_timeout_secs: u64,
+    pub query_timeout_secs: u64,               // the longest the database may spend on one query
+
+    // data
+    pub datasets: Vec<DatasetSpec>,
+    pub argo_collection: String,
+    pub argo_metadata_collection: String,
+
+    // limits
+    pub page_size: i64,          // used when a search doesn't set pageSize
+    pub max_page_size: i64,      // the largest pageSize a search may ask for
+    pub max_response_bytes: u64, // searches estimated to return more per page are refused
+
+    // api keys and rate limiting
+    pub api_key_header: String,
+    pub api_keys_collection: String,     // documents like {key, tier}
+    pub api_keys_file: Option<String>,   // a json object of key: tier, used instead of the collection when set
+    pub anonymous_tier: String,          // for requests without a key
+    pub address_tier: String,            // what each client address may send in all, with or without keys
+    pub rate_limits: HashMap<String, RateLimit>,
+    pub trust_proxy_headers: bool,       // take client addresses from Forwarded / X-Forwarded-For
+
+    // logging and tracing
+    pub log_format: LogFormat,
+    pub log_filter: String,              // tracing-subscriber directives, like "info" or "info,api=debug"
+    pub otlp_endpoint: Option<String>,   // where to send spans over OTLP/HTTP, like http://localhost:4318/v1/traces
+    pub service_name: String,            // as reported to the trace collector
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Json,
+    Text,
+}
+
+// a token bucket: `burst` requests at once, refilled at `per_second`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub burst: f64,
+    pub per_second: f64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            bind_address: String::from("0.0.0.0:8080"),
+            workers: None,
+            client_request_timeout_secs: 5,
+            shutdown_timeout_secs: 30,
+            mongodb_uri: None,
+            database: String::from("argo"),
+            mongo_min_pool_size: None,
+            mongo_max_pool_size: None,
+            mongo_connect_timeout_secs: 10,
+            mongo_server_selection_timeout_secs: 30,
+            query_timeout_secs: 60,
+            datasets: datasets::timeseries_datasets(),
+            argo_collection: String::from("argo"),
+            argo_metadata_collection: String::from("argoMeta"),
+            page_size: 1000,
+            max_page_size: 10000,
+            max_response_bytes: 100_000_000,
+            api_key_header: String::from("x-argokey"),
+            api_keys_collection: String::from("apiKeys"),
+            api_keys_file: None,
+            anonymous_tier: String::from("anonymous"),
+            address_tier: String::from("bulk"),
+            rate_limits: HashMap::from([
+                (String::from("anonymous"), RateLimit { burst: 10.0, per_second: 1.0 }),
+                (String::from("standard"), RateLimit { burst: 50.0, per_second: 5.0 }),
+                (String::from("bulk"), RateLimit { burst: 200.0, per_second: 20.0 }),
+            ]),
+            trust_proxy_headers: false,
+            log_format: LogFormat::Json,
+            log_filter: String::from("info"),
+            otlp_endpoint: None,
+            service_name: String::from("argovis-api"),
+        }
+    }
+}
+
+impl Config {
+    // the config file, if there is one, then environment overrides, then validation.
+    // every problem found is reported, rather than bailing out on the first one
+    pub fn load() -> Result<Config, Vec<String>> {
+        let path = std::env::var(format!("{}CONFIG", ENV_PREFIX)).ok();
+        let text = match &path {
+            Some(path) => std::fs::read_to_string(path).map_err(|e| vec![format!("can't read config file {}: {}", path, e)])?,
+            None => std::fs::read_to_string(CONFIG_FILE).unwrap_or_default(),
+        };
+
+        Config::from_sources(&text, std::env::vars())
+    }
+
+    pub fn from_sources(toml_text: &str, env: impl Iterator<Item = (String, String)>) -> Result<Config, Vec<String>> {
+        let mut table: toml::Table = toml::from_str(toml_text).map_err(|e| vec![format!("config file isn't valid: {}", e.to_string().trim_end())])?;
+
+        let fields = field_names();
+        let mut problems = Vec::new();
+        for (name, value) in env {
+            let key = match name.as_str() {
+                "MONGODB_URI" => String::from("mongodb_uri"),
+                _ => match name.strip_prefix(ENV_PREFIX).map(str::to_lowercase) {
+                    Some(key) if fields.contains(&key) => key,
+                    _ => continue,
+                },
+            };
+            match env_value(&key, &value) {
+                Some(value) => { table.insert(key, value); },
+                None => problems.push(format!("{} should be a single value, like 500, true or 0.0.0.0:8080", name)),
+            }
+        }
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        let config: Config = toml::Value::Table(table).try_into().map_err(|e| vec![format!("config isn't valid: {}", e.to_string().trim_end())])?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut problems = Vec::new();
+
+        if self.bind_address.parse::<SocketAddr>().is_err() {
+            problems.push(format!("bind_address should be an address and port, like 0.0.0.0:8080, not '{}'", self.bind_address));
+        }
+        if self.workers == Some(0) {
+            problems.push(String::from("workers should be at least 1"));
+        }
+        if self.mongodb_uri.is_none() {
+            problems.push(String::from("mongodb_uri should be set, in the config file or as MONGODB_URI"));
+        }
+        if let (Some(min), Some(max)) = (self.mongo_min_pool_size, self.mongo_max_pool_size) {
+            if min > max {
+                problems.push(String::from("mongo_min_pool_size should be no more than mongo_max_pool_size"));
+            }
+        }
+        if self.page_size < 1 || self.page_size > self.max_page_size {
+            problems.push(String::from("page_size should be between 1 and max_page_size"));
+        }
+
+        let mut names = HashSet::new();
+        for dataset in &self.datasets {
+            if dataset.name.is_empty() || dataset.collection.is_empty() {
+                problems.push(String::from("every dataset should have a name and a collection"));
+            }
+            if !names.insert(&dataset.name) {
+                problems.push(format!("dataset '{}' is defined more than once", dataset.name));
+            }
+        }
+
+        if !self.rate_limits.contains_key(&self.anonymous_tier) {
+            problems.push(format!("rate_limits should have an entry for the anonymous tier, '{}'", self.anonymous_tier));
+        }
+        if !self.rate_limits.contains_key(&self.address_tier) {
+            problems.push(format!("rate_limits should have an entry for the address tier, '{}'", self.address_tier));
+        }
+        for (tier, limit) in &self.rate_limits {
+            if limit.burst < 1.0 || limit.per_second <= 0.0 {
+                problems.push(format!("rate_limits.{} should allow a burst of at least 1 and a positive per_second", tier));
+            }
+        }
+
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log_filter) {
+            problems.push(format!("log_filter isn't valid: {}", e));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+// every field of Config, as named in the config file; None serializes to json as null, so optional fields are included
+fn field_names() -> HashSet<String> {
+    match serde_json::to_value(Config::default()) {
+        Ok(serde_json::Value::Object(fields)) => fields.into_iter().map(|(name, _)| name).collect(),
+        _ => HashSet::new(),
+    }
+}
+
+// environment values are taken as strings where the field is one, so ARGOVIS_DATABASE=2024 names a database,
+// and as toml scalars otherwise, like ARGOVIS_PAGE_SIZE=500 or ARGOVIS_TRUST_PROXY_HEADERS=true
+fn env_value(key: &str, value: &str) -> Option<toml::Value> {
+    let string = toml::Value::String(value.to_string());
+    if accepts(key, &string) {
+        return Some(string);
+    }
+
+    match toml::from_str::<toml::Table>(&format!("value = {}", value)) {
+        Ok(mut parsed) => parsed.remove("value").filter(|value| !value.is_table()),
+        Err(_) => Some(string),
+    }
+}
+
+// whether `key` set to `value`, and every other field left at its default, makes a Config
+fn accepts(key: &str, value: &toml::Value) -> bool {
+    let table = toml::Table::from_iter([(key.to_string(), value.clone())]);
+    toml::Value::Table(table).try_into::<Config>().is_ok()
+}
@@ -0,0 +1,91 @@
+use super::datasets::Dataset;
+use super::errors::ApiError;
+use super::helpers::{self, EARTH_RADIUS};
+use super::params::{Compression, SearchParams};
+use super::transforms;
+
+use std::f64::consts::PI;
+
+// rough json sizes, close enough to rank requests against a limit
+const BYTES_PER_DOCUMENT: f64 = 400.0; // _id, location, level, metadata and the rest, without data
+const BYTES_PER_VALUE: f64 = 10.0;
+
+// fraction of the globe's surface a search's region covers; 1 with no region at all.
+// data is assumed to be spread evenly over the globe, which is generous to regional products like BSOSE.
+pub fn area_fraction(params: &SearchParams) -> f64 {
+    let fraction = if let Some(polygon) = &params.polygon {
+        spherical_polygon_area(&helpers::validlonlat(polygon.clone())) / (4.0 * PI)
+    } else if let Some(boxregion) = &params.boxregion {
+        let corners = helpers::validlonlat(boxregion.clone());
+        let (sw, ne) = (&corners[0], &corners[1]);
+        // a box whose SW longitude is east of its NE longitude wraps over the dateline
+        let span = if sw[0] <= ne[0] { ne[0] - sw[0] } else { 360.0 - (sw[0] - ne[0]) };
+        span.to_radians() * (ne[1].to_radians().sin() - sw[1].to_radians().sin()).abs() / (4.0 * PI)
+    } else if let Some(radius) = params.radius {
+        (1.0 - (radius / EARTH_RADIUS).min(PI).cos()) / 2.0
+    } else {
+        1.0
+    };
+
+    fraction.clamp(0.0, 1.0)
+}
+
+// area of a closed ring of [lon, lat] degrees on the unit sphere
+fn spherical_polygon_area(ring: &[Vec<f64>]) -> f64 {
+    let sum: f64 = ring.windows(2).map(|pair| {
+        let (lon1, lat1) = (pair[0][0].to_radians(), pair[0][1].to_radians());
+        let (lon2, lat2) = (pair[1][0].to_radians(), pair[1][1].to_radians());
+        let mut dlon = lon2 - lon1;
+        if dlon > PI { dlon -= 2.0 * PI } else if dlon < -PI { dlon += 2.0 * PI }
+        dlon * (2.0 + lat1.sin() + lat2.sin())
+    }).sum();
+
+    (sum / 2.0).abs()
+}
+
+// fraction of the dataset's depth range a verticalRange covers; 1 for surface products
+pub fn vertical_fraction(params: &SearchParams, dataset: &Dataset) -> f64 {
+    match (&params.vertical_range, dataset.spec.vertical_extent) {
+        (Some(range), Some((top, bottom))) if bottom > top => {
+            let overlap = range[1].min(bottom) - range[0].max(top);
+            (overlap / (bottom - top)).clamp(0.0, 1.0)
+        },
+        _ => 1.0,
+    }
+}
+
+// how many bytes of json one page of this search would return
+pub fn estimate_timeseries_bytes(params: &SearchParams, dataset: &Dataset, page_size: i64) -> Result<f64, ApiError> {
+    let documents = match params.id {
+        Some(_) => 1.0,
+        None => dataset.document_count as f64 * area_fraction(params) * vertical_fraction(params, dataset),
+    }.min(page_size as f64);
+
+    // stubs and metadata carry no data values
+    let values_returned = params.compression != Some(Compression::Minimal)
+        && !params.batchmeta
+        && !params.data.contains(&String::from("except_data_values"));
+    let variables = match values_returned {
+        true => transforms::selected_data_info(&params.data, &dataset.data_info).0.len() as f64,
+        false => 0.0,
+    };
+    let timesteps = transforms::selected_window(params, &dataset.timeseries)?.len() as f64;
+
+    Ok(documents * (BYTES_PER_DOCUMENT + variables * timesteps * BYTES_PER_VALUE))
+}
+
+// turn away searches expected to return more than `limit` bytes, before they reach the database
+pub fn check_timeseries_cost(params: &SearchParams, dataset: &Dataset, page_size: i64, limit: u64) -> Result<(), ApiError> {
+    let estimate = estimate_timeseries_bytes(params, dataset, page_size)?;
+    if estimate <= limit as f64 {
+        return Ok(());
+    }
+
+    Err(ApiError::PayloadTooLarge(format!(
+        "This search would return about {:.1} MB per page, more than the {:.1} MB limit. \
+         Narrow it with a smaller polygon, box or radius, a verticalRange, a shorter startDate to endDate window or mostrecent, \
+         fewer variables in 'data', or a smaller pageSize.",
+        estimate / 1e6,
+        limit as f64 / 1e6,
+    )))
+}
@@ -0,0 +1,136 @@
+use super::helpers;
+use super::schema;
+
+use mongodb::bson::{Bson, DateTime as BsonDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use utoipa::ToSchema;
+
+// which schema struct a dataset's data documents deserialize into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SchemaKind {
+    Bsose,
+    Grid,
+}
+
+// everything needed to serve a gridded timeseries product at /timeseries/{name}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetSpec {
+    pub name: String,
+    pub collection: String,
+    pub metadata_collection: String,
+    pub data_type: String, // data_type of the metadata document carrying the dataset's timeseries and data_info
+    pub schema: SchemaKind,
+    #[serde(default)]
+    pub vertical_extent: Option<(f64, f64)>, // (shallowest, deepest) level; None for surface products
+}
+
+impl DatasetSpec {
+    pub fn new(name: &str, collection: &str, metadata_collection: &str, data_type: &str, schema: SchemaKind) -> DatasetSpec {
+        DatasetSpec {
+            name: name.to_string(),
+            collection: collection.to_string(),
+            metadata_collection: metadata_collection.to_string(),
+            data_type: data_type.to_string(),
+            schema,
+            vertical_extent: None,
+        }
+    }
+
+    pub fn with_vertical_extent(mut self, shallowest: f64, deepest: f64) -> DatasetSpec {
+        self.vertical_extent = Some((shallowest, deepest));
+        self
+    }
+}
+
+// a registered dataset, with the metadata loaded for it
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub spec: DatasetSpec,
+    pub timeseries: Vec<BsonDateTime>,
+    pub data_info: schema::DataInfo,
+    pub document_count: u64, // as of loading; for estimating the cost of searches
+    pub date_updated: BsonDateTime, // date_updated_argovis of the metadata document
+    pub source: Vec<schema::SourceMeta>,
+}
+
+// distinct basin and level values of a dataset's data documents, as of loading, for /vocabulary.
+// these scan the whole collection, so they're loaded apart from the Dataset and can't hold up searches
+#[derive(Debug, Clone)]
+pub struct Vocabulary {
+    pub basins: Vec<Value>,
+    pub levels: Vec<Value>,
+}
+
+impl Dataset {
+    // what's known about the dataset without querying its data
+    pub fn summary(&self) -> DatasetSummary {
+        DatasetSummary {
+            dataset: self.spec.name.clone(),
+            data_type: self.spec.data_type.clone(),
+            timeseries: TimeseriesExtent {
+                start: self.timeseries.first().map(helpers::bsondate2string),
+                end: self.timeseries.last().map(helpers::bsondate2string),
+                steps: self.timeseries.len(),
+            },
+            data_info: self.data_info.clone(),
+            source: self.source.clone(),
+            date_updated_argovis: helpers::bsondate2string(&self.date_updated),
+            document_count: self.document_count,
+            vertical_extent: self.spec.vertical_extent.map(|(shallowest, deepest)| [shallowest, deepest]),
+        }
+    }
+}
+
+// served at /timeseries/{name}/summary
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DatasetSummary {
+    pub dataset: String,
+    pub data_type: String,
+    pub timeseries: TimeseriesExtent,
+    #[schema(schema_with = schema::data_info_schema)]
+    pub data_info: schema::DataInfo,
+    pub source: Vec<schema::SourceMeta>,
+    #[schema(format = DateTime)]
+    pub date_updated_argovis: String,
+    pub document_count: u64, // estimated, as of loading
+    pub vertical_extent: Option<[f64; 2]>, // [shallowest, deepest] level in meters
+}
+
+// first and last timestep, as YYYY-MM-DDTHH:MM:SSZ, and how many there are
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimeseriesExtent {
+    #[schema(format = DateTime)]
+    pub start: Option<String>,
+    #[schema(format = DateTime)]
+    pub end: Option<String>,
+    pub steps: usize,
+}
+
+// the timeseries products served when the configuration doesn't list its own
+pub fn timeseries_datasets() -> Vec<DatasetSpec> {
+    vec![
+        // roughly the depth of BSOSE's deepest level, in meters
+        DatasetSpec::new("bsose", "bsose", "timeseriesMeta", "BSOSE-profile", SchemaKind::Bsose).with_vertical_extent(0.0, 5575.0),
+        DatasetSpec::new("noaasst", "noaaOIsst", "timeseriesMeta", "noaa-oi-sst-v2", SchemaKind::Grid),
+        DatasetSpec::new("copernicussla", "copernicusSLA", "timeseriesMeta", "sea-level-anomaly", SchemaKind::Grid),
+        DatasetSpec::new("ccmpwind", "ccmpwind", "timeseriesMeta", "ccmp-wind", SchemaKind::Grid),
+    ]
+}
+
+// distinct values in ascending order, as json; basins and levels are stored as doubles, but may come back as integers
+pub fn sorted_values(mut values: Vec<Bson>) -> Vec<Value> {
+    values.sort_by(|a, b| number(a).partial_cmp(&number(b)).unwrap_or(Ordering::Equal));
+    values.into_iter().map(Bson::into_relaxed_extjson).collect()
+}
+
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(x) => Some(*x),
+        Bson::Int32(x) => Some(*x as f64),
+        Bson::Int64(x) => Some(*x as f64),
+        _ => None,
+    }
+}
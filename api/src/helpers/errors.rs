@@ -0,0 +1,168 @@
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt;
+use utoipa::ToSchema;
+
+// every failure a request can run into; handlers return Result<_, ApiError> and use `?`
+#[derive(Debug)]
+pub enum ApiError {
+    Validation(Vec<Problem>),
+    NotFound(String),
+    Unauthorized(String),
+    RateLimited(u64), // seconds until the client may retry
+    PayloadTooLarge(String),
+    Unavailable(String), // the server isn't ready to answer this yet, like a dataset still loading
+    Database(mongodb::error::Error),
+    Internal(String),
+}
+
+// one thing wrong with a request's parameters: the rule it breaks, a short fixed code like 'box' or
+// 'date_order' to count it by, and a message saying what to change
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Problem {
+    pub fn new(rule: &'static str, message: impl Into<String>) -> Problem {
+        Problem { rule, message: message.into() }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// the json body of every error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Machine-readable, like validation_error or not_found
+    pub code: &'static str,
+    pub message: String,
+    /// Every problem found with the query parameters, for validation errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Vec<String>>,
+}
+
+impl ApiError {
+    // machine-readable code, stable across releases
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation(_) => "validation_error",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::Unavailable(_) => "unavailable",
+            ApiError::Database(_) => "database_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn invalid(rule: &'static str, message: impl Into<String>) -> ApiError {
+        ApiError::Validation(vec![Problem::new(rule, message)])
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Validation(problems) => write!(f, "Invalid query parameters: {}", problems.iter().map(|problem| problem.message.as_str()).collect::<Vec<_>>().join("; ")),
+            ApiError::NotFound(message) => write!(f, "{}", message),
+            ApiError::Unauthorized(message) => write!(f, "{}", message),
+            ApiError::RateLimited(seconds) => write!(f, "Too many requests; retry after {} seconds", seconds),
+            ApiError::PayloadTooLarge(message) => write!(f, "{}", message),
+            ApiError::Unavailable(message) => write!(f, "{}", message),
+            ApiError::Database(e) => write!(f, "Database error: {}", e),
+            ApiError::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<mongodb::error::Error> for ApiError {
+    fn from(e: mongodb::error::Error) -> ApiError {
+        ApiError::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::ser::Error> for ApiError {
+    fn from(e: mongodb::bson::ser::Error) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<mongodb::bson::de::Error> for ApiError {
+    fn from(e: mongodb::bson::de::Error) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<arrow::error::ArrowError> for ApiError {
+    fn from(e: arrow::error::ArrowError) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<parquet::errors::ParquetError> for ApiError {
+    fn from(e: parquet::errors::ParquetError) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<netcdf3::InvalidDataSet> for ApiError {
+    fn from(e: netcdf3::InvalidDataSet) -> ApiError {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl From<netcdf3::WriteError> for ApiError {
+    fn from(e: netcdf3::WriteError) -> ApiError {
+        ApiError::Internal(format!("{:?}", e))
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::Validation(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Database(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // don't leak database or internal details to clients; keep them in the server log
+        let (message, details) = match self {
+            ApiError::Validation(problems) => (String::from("Invalid query parameters"), Some(problems.iter().map(|problem| problem.message.clone()).collect())),
+            ApiError::NotFound(_) | ApiError::Unauthorized(_) | ApiError::RateLimited(_) | ApiError::PayloadTooLarge(_) | ApiError::Unavailable(_) => (self.to_string(), None),
+            ApiError::Database(_) | ApiError::Internal(_) => {
+                tracing::error!(code = self.code(), "{}", self);
+                (String::from("The server was unable to complete this request"), None)
+            },
+        };
+        let body = ErrorBody { code: self.code(), message, details };
+
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::RateLimited(seconds) = self {
+            response.insert_header(("Retry-After", seconds.to_string()));
+        }
+
+        response.json(body)
+    }
+}
@@ -1,60 +1,70 @@
-
 use super::helpers;
+use super::params::SearchParams;
+use super::errors::ApiError;
 
 use serde_json::json;
 use mongodb::bson;
+use mongodb::bson::DateTime as BsonDateTime;
+
+#[tracing::instrument(name = "build_filter", skip_all)]
+pub fn filter_timeseries(params: &SearchParams) -> Result<mongodb::bson::Document, ApiError> {
+    // Construct the filter
+    let mut filter = spatial_filter(params)?;
+    if let Some(vertical_range) = &params.vertical_range {
+        filter = vertical_range_filter(vertical_range, filter);
+    }
 
-pub fn filter_timeseries(params: serde_json::Value) -> mongodb::bson::Document {
-    // Extract the query parameters
-    let polygon = params.get("polygon").map(|p| p.as_str().unwrap());
-    let boxregion = params.get("box").map(|p| p.as_str().unwrap());
-    let center = params.get("center").map(|p| p.as_str().unwrap());
-    let radius = params.get("radius").map(|d| d.as_str().unwrap().parse::<f64>().unwrap());
-    let id = params.get("id").map(|p| p.as_str().unwrap());
-    let vertical_range = params.get("verticalRange").map(|p| p.as_str().unwrap());
+    Ok(filter)
+}
 
+// point data stores one document per time, so dates are filtered in the query rather than by slicing.
+// levels live inside each document's data, so verticalRange is applied by transforms::slice_levels instead.
+#[tracing::instrument(name = "build_filter", skip_all)]
+pub fn filter_points(params: &SearchParams) -> Result<mongodb::bson::Document, ApiError> {
     // Construct the filter
+    let mut filter = spatial_filter(params)?;
+    if params.start_date.is_some() || params.end_date.is_some() {
+        filter = timestamp_filter(params.start_date, params.end_date, filter);
+    }
+
+    Ok(filter)
+}
+
+fn spatial_filter(params: &SearchParams) -> Result<mongodb::bson::Document, ApiError> {
     let mut filter = mongodb::bson::doc! {};
-    if let Some(id) = id {
+    if let Some(id) = &params.id {
         filter = id_filter(id, filter);
     }
-    if let Some(polygon) = polygon {
-        filter = polygon_filter(polygon, filter);
+    if let Some(polygon) = &params.polygon {
+        filter = polygon_filter(polygon.clone(), filter)?;
     }
-    if let Some(boxregion) = boxregion {
-        filter = box_filter(boxregion, filter);
+    if let Some(boxregion) = &params.boxregion {
+        filter = box_filter(boxregion.clone(), filter);
     }
-    if let (Some(center), Some(radius)) = (center, radius) {
-        filter = center_filter(center, radius, filter);
-    }
-    if let Some(vertical_range) = vertical_range {
-        filter = vertical_range_filter(vertical_range, filter);
+    if let (Some(center), Some(radius)) = (&params.center, params.radius) {
+        filter = center_filter(center.clone(), radius, filter);
     }
 
-    return filter;
+    Ok(filter)
 }
 
-fn polygon_filter(polygon: &str, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
-    let mut polygon_coordinates: Vec<Vec<f64>> = serde_json::from_str(polygon).unwrap();
-
+fn polygon_filter(polygon: Vec<Vec<f64>>, mut filter: mongodb::bson::Document) -> Result<mongodb::bson::Document, ApiError> {
     // coordinate sanitation
-    polygon_coordinates = helpers::validlonlat(polygon_coordinates);
+    let polygon_coordinates = helpers::validlonlat(polygon);
 
     // filter construction
     let polygon_geojson = bson::to_bson(&json!({ 
         "type": "Polygon",
         "coordinates": [polygon_coordinates]
-    })).unwrap();
+    }))?;
     filter.insert("geolocation", mongodb::bson::doc! { "$geoWithin": { "$geometry": polygon_geojson } });
 
-    filter
+    Ok(filter)
 }
 
-fn box_filter(boxregion: &str, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
-    let mut box_coordinates: Vec<Vec<f64>> = serde_json::from_str(boxregion).unwrap();
-
+fn box_filter(boxregion: Vec<Vec<f64>>, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
     // coordinate sanitation
-    box_coordinates = helpers::validlonlat(box_coordinates);
+    let box_coordinates = helpers::validlonlat(boxregion);
 
     // box might cross dateline, need to split into two boxes
     let box_list = if box_coordinates[0][0] > box_coordinates[1][0] {
@@ -83,11 +93,9 @@ fn box_filter(boxregion: &str, mut filter: mongodb::bson::Document) -> mongodb::
     filter
 }
 
-fn center_filter(center: &str, radius: f64, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
-    let center_coordinates: Vec<f64> = serde_json::from_str(center).unwrap();
-
+fn center_filter(center: Vec<f64>, radius: f64, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
     // coordinate sanitation
-    let center_coordinates = helpers::validlonlat(vec![center_coordinates])[0].clone();
+    let center_coordinates = helpers::validlonlat(vec![center])[0].clone();
 
     // filter construction
     filter.insert("geolocation", mongodb::bson::doc! {
@@ -108,8 +116,19 @@ fn id_filter(id: &str, mut filter: mongodb::bson::Document) -> mongodb::bson::Do
     filter
 }
 
-fn vertical_range_filter(vertical_range: &str, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
-    let vertical_range: Vec<f64> = serde_json::from_str(vertical_range).unwrap();
+fn vertical_range_filter(vertical_range: &[f64], mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
     filter.insert("level", mongodb::bson::doc! { "$gte": vertical_range[0], "$lt": vertical_range[1] });
     filter
+}
+
+fn timestamp_filter(start_date: Option<BsonDateTime>, end_date: Option<BsonDateTime>, mut filter: mongodb::bson::Document) -> mongodb::bson::Document {
+    let mut range = mongodb::bson::doc! {};
+    if let Some(start_date) = start_date {
+        range.insert("$gte", start_date);
+    }
+    if let Some(end_date) = end_date {
+        range.insert("$lt", end_date);
+    }
+    filter.insert("timestamp", range);
+    filter
 }
\ No newline at end of file
@@ -0,0 +1,257 @@
+use super::errors::ApiError;
+use super::helpers;
+use super::schema;
+use super::telemetry;
+
+use actix_web::{web::Bytes, HttpResponse};
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use prometheus::IntCounter;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// results transformed one document at a time, as they come off the cursor
+pub type RecordStream<T> = BoxStream<'static, Result<T, ApiError>>;
+
+// waits for the first record before committing to a response, so a failure up front still gets its own status;
+// a failure after that can only cut the body short. None when there are no records at all.
+pub async fn peek<T: Send + 'static>(mut records: RecordStream<T>) -> Result<Option<RecordStream<T>>, ApiError> {
+    match records.next().await {
+        Some(Ok(first)) => Ok(Some(stream::once(future::ready(Ok(first))).chain(records).boxed())),
+        Some(Err(e)) => Err(e),
+        None => Ok(None),
+    }
+}
+
+// as peek, but no results is a 404
+pub async fn first_or_not_found<T: Send + 'static>(records: RecordStream<T>) -> Result<RecordStream<T>, ApiError> {
+    peek(records).await?.ok_or_else(|| ApiError::NotFound(String::from("No results found")))
+}
+
+// counts records into `counter` as they pass, for metrics
+pub fn counted<T: Send + 'static>(records: RecordStream<T>, counter: IntCounter) -> RecordStream<T> {
+    records.inspect(move |record| if record.is_ok() { counter.inc() }).boxed()
+}
+
+// streamed bodies are written after the handler returns; this span covers writing them,
+// and so also reading and transforming the records they're written from
+fn serialize_span(format: &'static str) -> tracing::Span {
+    tracing::info_span!("serialize", format)
+}
+
+// streamed json ////////////////////////////////////////////////////////////
+
+// one json document per line
+pub async fn ndjson<T: Serialize + Send + 'static>(records: RecordStream<T>) -> Result<HttpResponse, ApiError> {
+    let records = first_or_not_found(records).await?;
+
+    let lines = records.map(|record| {
+        let mut line = serde_json::to_vec(&record?)?;
+        line.push(b'\n');
+        Ok::<_, ApiError>(Bytes::from(line))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(telemetry::in_span(lines.boxed(), serialize_span("ndjson"))))
+}
+
+// the same body create_response would build, written one element at a time
+pub async fn json_array<T: Serialize + Send + 'static>(records: RecordStream<T>) -> Result<HttpResponse, ApiError> {
+    let records = first_or_not_found(records).await?;
+
+    let elements = records.enumerate().map(|(i, record)| {
+        let mut element = if i == 0 { vec![b'['] } else { vec![b','] };
+        element.extend(serde_json::to_vec(&record?)?);
+        Ok::<_, ApiError>(Bytes::from(element))
+    });
+    let close = stream::once(future::ready(Ok(Bytes::from_static(b"]"))));
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .streaming(telemetry::in_span(elements.chain(close).boxed(), serialize_span("json"))))
+}
+
+// everything returned alongside the data in envelope mode
+pub struct Envelope {
+    pub next: Option<String>,
+    pub query: Value,                           // the search parameters, as normalized for the query
+    pub data_info: Option<schema::DataInfo>,    // when every record shares one
+    pub timeseries: Option<Vec<String>>,        // when every record shares one
+    pub shared: Vec<&'static str>,              // record fields the envelope carries once instead
+}
+
+// {next, query, data_info, timeseries, data, count}; an empty page is an empty data array, not a 404
+pub async fn json_envelope<T: Serialize + Send + 'static>(records: RecordStream<T>, envelope: Envelope) -> Result<HttpResponse, ApiError> {
+    let records = peek(records).await?.unwrap_or_else(|| stream::empty().boxed());
+
+    let head = format!(
+        "{{\"next\":{},\"query\":{},\"data_info\":{},\"timeseries\":{},\"data\":[",
+        serde_json::to_string(&envelope.next)?,
+        serde_json::to_string(&envelope.query)?,
+        serde_json::to_string(&envelope.data_info)?,
+        serde_json::to_string(&envelope.timeseries)?,
+    );
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let counted = count.clone();
+    let shared = envelope.shared;
+    let elements = records.enumerate().map(move |(i, record)| {
+        let mut record = serde_json::to_value(record?)?;
+        if let Value::Object(fields) = &mut record {
+            shared.iter().for_each(|key| { fields.remove(*key); });
+        }
+        counted.fetch_add(1, Ordering::Relaxed);
+
+        let mut element = if i == 0 { Vec::new() } else { vec![b','] };
+        element.extend(serde_json::to_vec(&record)?);
+        Ok::<_, ApiError>(Bytes::from(element))
+    });
+
+    // the count is only known once every record has been written
+    let tail = stream::once(async move { Ok(Bytes::from(format!("],\"count\":{}}}", count.load(Ordering::Relaxed)))) });
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .streaming(telemetry::in_span(stream::once(future::ready(Ok(Bytes::from(head)))).chain(elements).chain(tail).boxed(), serialize_span("json"))))
+}
+
+// geojson ////////////////////////////////////////////////////////////////////
+
+// each record becomes a Feature: its geolocation is the geometry, its _id the feature id, everything else a property
+#[tracing::instrument(name = "serialize", skip_all, fields(format = "geojson"))]
+pub fn feature_collection<T: Serialize>(results: &[T]) -> Result<HttpResponse, ApiError> {
+    let features = results.iter().map(|result| {
+        let mut properties = match serde_json::to_value(result)? {
+            Value::Object(properties) => properties,
+            _ => return Err(ApiError::Internal(String::from("geojson features need records that serialize as objects"))),
+        };
+        let geometry = properties.remove("geolocation").unwrap_or(Value::Null);
+        let id = properties.remove("_id").unwrap_or(Value::Null);
+
+        Ok(feature(id, geometry, properties))
+    }).collect::<Result<Vec<_>, ApiError>>()?;
+
+    geojson_response(features)
+}
+
+#[tracing::instrument(name = "serialize", skip_all, fields(format = "geojson"))]
+pub fn timeseries_stub_collection(stubs: &[schema::TimeseriesStub]) -> Result<HttpResponse, ApiError> {
+    let features = stubs.iter().map(|stub| {
+        let mut properties = Map::new();
+        properties.insert(String::from("level"), json!(stub.level));
+        properties.insert(String::from("metadata"), json!(stub.metadata));
+        feature(json!(stub._id), point(stub.longitude, stub.latitude), properties)
+    }).collect();
+
+    geojson_response(features)
+}
+
+#[tracing::instrument(name = "serialize", skip_all, fields(format = "geojson"))]
+pub fn point_stub_collection(stubs: &[schema::PointStub]) -> Result<HttpResponse, ApiError> {
+    let features = stubs.iter().map(|stub| {
+        let mut properties = Map::new();
+        properties.insert(String::from("timestamp"), json!(helpers::bsondate2string(&stub.timestamp)));
+        properties.insert(String::from("metadata"), json!(stub.metadata));
+        feature(json!(stub._id), point(stub.longitude, stub.latitude), properties)
+    }).collect();
+
+    geojson_response(features)
+}
+
+fn feature(id: Value, geometry: Value, properties: Map<String, Value>) -> Value {
+    json!({"type": "Feature", "id": id, "geometry": geometry, "properties": properties})
+}
+
+fn point(longitude: f64, latitude: f64) -> Value {
+    json!({"type": "Point", "coordinates": [longitude, latitude]})
+}
+
+fn geojson_response(features: Vec<Value>) -> Result<HttpResponse, ApiError> {
+    if features.is_empty() {
+        return Err(ApiError::NotFound(String::from("No results found")));
+    }
+
+    let collection = json!({"type": "FeatureCollection", "features": features});
+    Ok(HttpResponse::Ok()
+        .content_type("application/geo+json")
+        .body(serde_json::to_string(&collection)?))
+}
+
+// csv ////////////////////////////////////////////////////////////////////////
+
+// one row per (id, longitude, latitude, level, timestamp), one column per variable in data_info.0.
+// `timeseries` is used for results that were never sliced and so don't carry their own.
+pub async fn timeseries_csv<T: schema::IsTimeseries + Send + 'static>(records: RecordStream<T>, data_info: schema::DataInfo, timeseries: Vec<String>) -> Result<HttpResponse, ApiError> {
+    let records = first_or_not_found(records).await?;
+    let header = csv_header(&data_info);
+
+    // rows are rendered one result at a time, as the response is written
+    let rows = records.map(move |record| {
+        Ok::<_, ApiError>(Bytes::from(timeseries_csv_rows(&mut record?, &timeseries)))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .streaming(telemetry::in_span(stream::once(future::ready(Ok::<_, ApiError>(Bytes::from(header)))).chain(rows).boxed(), serialize_span("csv"))))
+}
+
+fn csv_header(data_info: &schema::DataInfo) -> String {
+    let units = data_info.1.iter().position(|x| x == "units");
+    let mut columns = vec![
+        String::from("_id"), String::from("longitude"), String::from("latitude"),
+        String::from("level"), String::from("timestamp"),
+    ];
+
+    for (i, variable) in data_info.0.iter().enumerate() {
+        match units.and_then(|u| data_info.2.get(i)?.get(u)) {
+            Some(unit) => columns.push(format!("{} [{}]", variable, unit)),
+            None => columns.push(variable.clone()),
+        }
+    }
+
+    csv_line(&columns)
+}
+
+fn timeseries_csv_rows<T: schema::IsTimeseries>(result: &mut T, default_timeseries: &[String]) -> String {
+    let prefix = [
+        result._id(),
+        result.longitude().to_string(),
+        result.latitude().to_string(),
+        result.level().to_string(),
+    ];
+    let timeseries = result.timeseries().map(|t| t.clone()).unwrap_or_else(|| default_timeseries.to_vec());
+    let data = result.data();
+
+    let mut rows = String::new();
+    for (i, timestamp) in timeseries.iter().enumerate() {
+        let mut fields = prefix.to_vec();
+        fields.push(timestamp.clone());
+        fields.extend(data.iter().map(|variable| csv_number(variable.get(i).copied())));
+        rows.push_str(&csv_line(&fields));
+    }
+
+    rows
+}
+
+// missing values are left empty
+fn csv_number(x: Option<f64>) -> String {
+    match x {
+        Some(x) if !x.is_nan() => x.to_string(),
+        _ => String::new(),
+    }
+}
+
+fn csv_line(fields: &[String]) -> String {
+    let escaped: Vec<String> = fields.iter().map(|field| {
+        if field.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.clone()
+        }
+    }).collect();
+
+    format!("{}\r\n", escaped.join(","))
+}
@@ -0,0 +1,300 @@
+use super::errors::ApiError;
+use super::helpers::EARTH_RADIUS;
+use super::store::{DataStore, DocumentStream, QueryOptions};
+
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use mongodb::bson::{Bson, Document};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// an in-memory stand-in for MongoDB, for running the API without a database.
+// evaluates the filter shapes filters.rs produces: equality, comparison and $in operators,
+// $or / $and, $geoWithin with a $geometry polygon or a $box, and $near with $maxDistance.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    collections: HashMap<String, Vec<Document>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    pub fn insert(&mut self, collection: &str, documents: Vec<Document>) {
+        self.collections.entry(collection.to_string()).or_default().extend(documents);
+    }
+
+    pub fn with_documents(mut self, collection: &str, documents: Vec<Document>) -> MemoryStore {
+        self.insert(collection, documents);
+        self
+    }
+}
+
+#[async_trait]
+impl DataStore for MemoryStore {
+    async fn find(&self, collection: &str, filter: Document, options: QueryOptions) -> Result<DocumentStream, ApiError> {
+        let mut results = Vec::new();
+        for document in self.collections.get(collection).into_iter().flatten() {
+            if matches(document, &filter)? {
+                results.push(document.clone());
+            }
+        }
+
+        if let Some((field, direction)) = &options.sort {
+            results.sort_by(|a, b| {
+                let ordering = match (lookup(a, field), lookup(b, field)) {
+                    (Some(x), Some(y)) => compare(x, y).unwrap_or(Ordering::Equal),
+                    (Some(_), None) => Ordering::Greater,
+                    (None, Some(_)) => Ordering::Less,
+                    (None, None) => Ordering::Equal,
+                };
+                if *direction < 0 { ordering.reverse() } else { ordering }
+            });
+        }
+
+        let skip = options.skip.unwrap_or(0) as usize;
+        let limit = match options.limit {
+            Some(limit) if limit > 0 => limit as usize,
+            _ => usize::MAX,
+        };
+        let page: Vec<Result<Document, ApiError>> = results.into_iter().skip(skip).take(limit)
+            .map(|document| Ok(match &options.projection {
+                Some(projection) => project(document, projection),
+                None => document,
+            }))
+            .collect();
+
+        Ok(stream::iter(page).boxed())
+    }
+
+    async fn estimated_count(&self, collection: &str) -> Result<u64, ApiError> {
+        Ok(self.collections.get(collection).map_or(0, |documents| documents.len() as u64))
+    }
+
+    async fn distinct(&self, collection: &str, field: &str, filter: Document) -> Result<Vec<Bson>, ApiError> {
+        let mut values: Vec<Bson> = Vec::new();
+        for document in self.collections.get(collection).into_iter().flatten() {
+            if !matches(document, &filter)? {
+                continue;
+            }
+            let found = match lookup(document, field) {
+                Some(Bson::Array(elements)) => elements.clone(),
+                Some(value) => vec![value.clone()],
+                None => Vec::new(),
+            };
+            for value in found {
+                if !values.iter().any(|v| equals(v, &value)) {
+                    values.push(value);
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+}
+
+// inclusion projections only; _id is kept unless it's explicitly excluded
+fn project(document: Document, projection: &Document) -> Document {
+    let included = |key: &str| match projection.get(key) {
+        Some(Bson::Boolean(flag)) => *flag,
+        Some(flag) => number(flag) != Some(0.0),
+        None => key == "_id",
+    };
+
+    document.into_iter().filter(|(key, _)| included(key)).collect()
+}
+
+// filter evaluation //////////////////////////////////////////////////////////
+
+pub fn matches(document: &Document, filter: &Document) -> Result<bool, ApiError> {
+    for (key, condition) in filter {
+        let ok = match key.as_str() {
+            "$or" => any_clause(document, condition)?,
+            "$and" => all_clauses(document, condition)?,
+            _ => {
+                let value = lookup(document, key);
+                match condition {
+                    Bson::Document(operators) if operators.keys().all(|k| k.starts_with('$')) => {
+                        let mut ok = true;
+                        for (operator, argument) in operators {
+                            ok = ok && apply(operator, argument, value)?;
+                        }
+                        ok
+                    },
+                    _ => value.is_some_and(|v| equals(v, condition)),
+                }
+            },
+        };
+        if !ok {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn clauses(condition: &Bson) -> Result<Vec<&Document>, ApiError> {
+    match condition {
+        Bson::Array(items) => items.iter().map(|item| match item {
+            Bson::Document(clause) => Ok(clause),
+            _ => Err(ApiError::Internal(String::from("logical operators take an array of filter documents"))),
+        }).collect(),
+        _ => Err(ApiError::Internal(String::from("logical operators take an array of filter documents"))),
+    }
+}
+
+fn any_clause(document: &Document, condition: &Bson) -> Result<bool, ApiError> {
+    for clause in clauses(condition)? {
+        if matches(document, clause)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+fn all_clauses(document: &Document, condition: &Bson) -> Result<bool, ApiError> {
+    for clause in clauses(condition)? {
+        if !matches(document, clause)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// resolve a dotted path like geolocation.coordinates
+fn lookup<'a>(document: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut parts = path.split('.');
+    let mut value = document.get(parts.next()?)?;
+    for part in parts {
+        value = match value {
+            Bson::Document(inner) => inner.get(part)?,
+            Bson::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+fn apply(operator: &str, argument: &Bson, value: Option<&Bson>) -> Result<bool, ApiError> {
+    let ok = match operator {
+        "$eq" => value.is_some_and(|v| equals(v, argument)),
+        "$ne" => !value.is_some_and(|v| equals(v, argument)),
+        "$gt" => value.is_some_and(|v| ordered(v, argument, |o| o == Ordering::Greater)),
+        "$gte" => value.is_some_and(|v| ordered(v, argument, |o| o != Ordering::Less)),
+        "$lt" => value.is_some_and(|v| ordered(v, argument, |o| o == Ordering::Less)),
+        "$lte" => value.is_some_and(|v| ordered(v, argument, |o| o != Ordering::Greater)),
+        "$in" => match argument {
+            Bson::Array(candidates) => value.is_some_and(|v| candidates.iter().any(|c| equals(v, c))),
+            _ => return Err(ApiError::Internal(String::from("$in takes an array"))),
+        },
+        "$exists" => value.is_some() == argument.as_bool().unwrap_or(true),
+        "$geoWithin" => value.and_then(point).is_some_and(|p| within(p, argument).unwrap_or(false)),
+        "$near" => match value.and_then(point) {
+            Some(p) => near(p, argument)?,
+            None => false,
+        },
+        _ => return Err(ApiError::Internal(format!("in-memory store does not support {}", operator))),
+    };
+
+    Ok(ok)
+}
+
+// arrays match a scalar if any element does, as in MongoDB
+fn equals(value: &Bson, target: &Bson) -> bool {
+    match (value, target) {
+        (Bson::Array(items), target) if !matches!(target, Bson::Array(_)) => items.iter().any(|item| equals(item, target)),
+        _ => compare(value, target) == Some(Ordering::Equal) || value == target,
+    }
+}
+
+fn ordered(value: &Bson, target: &Bson, accept: impl Fn(Ordering) -> bool) -> bool {
+    match value {
+        Bson::Array(items) => items.iter().any(|item| compare(item, target).is_some_and(&accept)),
+        _ => compare(value, target).is_some_and(accept),
+    }
+}
+
+fn number(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Double(x) => Some(*x),
+        Bson::Int32(x) => Some(*x as f64),
+        Bson::Int64(x) => Some(*x as f64),
+        _ => None,
+    }
+}
+
+fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    match (a, b) {
+        (Bson::String(x), Bson::String(y)) => Some(x.cmp(y)),
+        (Bson::DateTime(x), Bson::DateTime(y)) => Some(x.cmp(y)),
+        (Bson::Boolean(x), Bson::Boolean(y)) => Some(x.cmp(y)),
+        _ => number(a)?.partial_cmp(&number(b)?),
+    }
+}
+
+// a [lon, lat] pair, either bare or as the coordinates of a GeoJSON point
+fn point(value: &Bson) -> Option<(f64, f64)> {
+    let coordinates = match value {
+        Bson::Document(geojson) => geojson.get_array("coordinates").ok()?,
+        Bson::Array(coordinates) => coordinates,
+        _ => return None,
+    };
+    Some((number(coordinates.first()?)?, number(coordinates.get(1)?)?))
+}
+
+fn points(value: &Bson) -> Option<Vec<(f64, f64)>> {
+    match value {
+        Bson::Array(items) => items.iter().map(point).collect(),
+        _ => None,
+    }
+}
+
+fn within(p: (f64, f64), argument: &Bson) -> Option<bool> {
+    let argument = argument.as_document()?;
+
+    if let Ok(geometry) = argument.get_document("$geometry") {
+        let ring = points(geometry.get_array("coordinates").ok()?.first()?)?;
+        return Some(in_polygon(p, &ring));
+    }
+
+    let corners = points(argument.get("$box")?)?;
+    let (sw, ne) = (corners.first()?, corners.get(1)?);
+    Some(p.0 >= sw.0 && p.0 <= ne.0 && p.1 >= sw.1 && p.1 <= ne.1)
+}
+
+// planar ray casting; MongoDB draws $geometry edges as great circles, which only matters for very large polygons
+fn in_polygon(p: (f64, f64), ring: &[(f64, f64)]) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > p.1) != (yj > p.1) && p.0 < (xj - xi) * (p.1 - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+// $near also sorts by distance in MongoDB; handlers always give an explicit sort, so only the distance cut is modelled
+fn near(p: (f64, f64), argument: &Bson) -> Result<bool, ApiError> {
+    let malformed = || ApiError::Internal(String::from("$near takes a $geometry point and a $maxDistance"));
+    let argument = argument.as_document().ok_or_else(malformed)?;
+    let center = argument.get("$geometry").and_then(point).ok_or_else(malformed)?;
+    let max_distance = argument.get("$maxDistance").and_then(number).unwrap_or(f64::INFINITY);
+
+    Ok(haversine(p, center) <= max_distance)
+}
+
+fn haversine(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lon1, lat1) = (a.0.to_radians(), a.1.to_radians());
+    let (lon2, lat2) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
@@ -0,0 +1,163 @@
+use super::errors::ApiError;
+use super::state::AppState;
+use super::store::{DataStore, DocumentStream, QueryOptions};
+
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web::{self, Bytes};
+use async_trait::async_trait;
+use mongodb::bson::{Bson, Document};
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+// every metric the API exports at /metrics
+pub struct Metrics {
+    registry: Registry,
+    pub requests: IntCounterVec,            // by route, method and status
+    pub request_duration: HistogramVec,     // by route, method and status; until the last byte of the body is sent
+    pub response_bytes: IntCounterVec,      // by route, method and status
+    pub query_duration: HistogramVec,       // by collection; until the database opens a cursor
+    pub documents: IntCounterVec,           // by dataset; records returned to clients, after filtering
+    pub validation_failures: IntCounterVec, // by rule; see errors::Problem
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let request_labels = ["route", "method", "status"];
+        let requests = IntCounterVec::new(Opts::new("argovis_http_requests_total", "HTTP requests answered"), &request_labels).unwrap();
+        let request_duration = HistogramVec::new(HistogramOpts::new("argovis_http_request_duration_seconds", "Time to answer HTTP requests, including streaming the body"), &request_labels).unwrap();
+        let response_bytes = IntCounterVec::new(Opts::new("argovis_http_response_bytes_total", "Bytes of response bodies serialized"), &request_labels).unwrap();
+        let query_duration = HistogramVec::new(HistogramOpts::new("argovis_db_query_duration_seconds", "Time for the database to open a cursor for a query"), &["collection"]).unwrap();
+        let documents = IntCounterVec::new(Opts::new("argovis_documents_returned_total", "Records returned by searches"), &["dataset"]).unwrap();
+        let validation_failures = IntCounterVec::new(Opts::new("argovis_validation_failures_total", "Problems found validating query parameters"), &["rule"]).unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(requests.clone())).unwrap();
+        registry.register(Box::new(request_duration.clone())).unwrap();
+        registry.register(Box::new(response_bytes.clone())).unwrap();
+        registry.register(Box::new(query_duration.clone())).unwrap();
+        registry.register(Box::new(documents.clone())).unwrap();
+        registry.register(Box::new(validation_failures.clone())).unwrap();
+
+        Metrics { registry, requests, request_duration, response_bytes, query_duration, documents, validation_failures }
+    }
+
+    // the prometheus text exposition format, and its content type
+    pub fn render(&self) -> Result<(String, &'static str), ApiError> {
+        let encoder = TextEncoder::new();
+        let text = encoder.encode_to_string(&self.registry.gather()).map_err(|e| ApiError::Internal(e.to_string()))?;
+        Ok((text, prometheus::TEXT_FORMAT))
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Metrics {
+        Metrics::new()
+    }
+}
+
+// query timing ///////////////////////////////////////////////////////////////
+
+// times every query made through another store
+pub struct MeteredStore {
+    store: Arc<dyn DataStore>,
+    metrics: Arc<Metrics>,
+}
+
+impl MeteredStore {
+    pub fn new(store: Arc<dyn DataStore>, metrics: Arc<Metrics>) -> MeteredStore {
+        MeteredStore { store, metrics }
+    }
+}
+
+#[async_trait]
+impl DataStore for MeteredStore {
+    async fn find(&self, collection: &str, filter: Document, options: QueryOptions) -> Result<DocumentStream, ApiError> {
+        let start = Instant::now();
+        let documents = self.store.find(collection, filter, options).await;
+        self.metrics.query_duration.with_label_values(&[collection]).observe(start.elapsed().as_secs_f64());
+        documents
+    }
+
+    async fn estimated_count(&self, collection: &str) -> Result<u64, ApiError> {
+        self.store.estimated_count(collection).await
+    }
+
+    async fn distinct(&self, collection: &str, field: &str, filter: Document) -> Result<Vec<Bson>, ApiError> {
+        let start = Instant::now();
+        let values = self.store.distinct(collection, field, filter).await;
+        self.metrics.query_duration.with_label_values(&[collection]).observe(start.elapsed().as_secs_f64());
+        values
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.store.ping().await
+    }
+}
+
+// middleware /////////////////////////////////////////////////////////////////
+
+// a response body that records its request's metrics once it has been sent, or abandoned
+pub struct CountedBody {
+    body: BoxBody,
+    bytes: u64,
+    recording: Option<Recording>,
+}
+
+struct Recording {
+    metrics: Arc<Metrics>,
+    labels: [String; 3],
+    start: Instant,
+}
+
+impl MessageBody for CountedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let chunk = Pin::new(&mut self.body).poll_next(cx);
+        if let Poll::Ready(Some(Ok(bytes))) = &chunk {
+            self.bytes += bytes.len() as u64;
+        }
+        chunk
+    }
+}
+
+impl Drop for CountedBody {
+    fn drop(&mut self) {
+        if let Some(recording) = &self.recording {
+            let labels = recording.labels.each_ref().map(String::as_str);
+            recording.metrics.requests.with_label_values(&labels).inc();
+            recording.metrics.request_duration.with_label_values(&labels).observe(recording.start.elapsed().as_secs_f64());
+            recording.metrics.response_bytes.with_label_values(&labels).inc_by(self.bytes);
+        }
+    }
+}
+
+// wrap the app in this with middleware::from_fn, outside everything else so rejected requests are counted too.
+// routes are labelled by their pattern, like /timeseries/{dataset}, so labels stay few
+pub async fn track(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<CountedBody>, actix_web::Error> {
+    let start = Instant::now();
+    let metrics = req.app_data::<web::Data<AppState>>().map(|state| state.metrics.clone());
+    let route = req.match_pattern().unwrap_or_else(|| String::from("unmatched"));
+    let method = req.method().to_string();
+
+    let response = next.call(req).await?;
+
+    if let (Some(metrics), Some(ApiError::Validation(problems))) = (&metrics, response.response().error().and_then(|e| e.as_error::<ApiError>())) {
+        for problem in problems {
+            metrics.validation_failures.with_label_values(&[problem.rule]).inc();
+        }
+    }
+
+    let status = response.status().as_str().to_string();
+    let recording = metrics.map(|metrics| Recording { metrics, labels: [route, method, status], start });
+    Ok(response.map_body(|_, body| CountedBody { body: body.boxed(), bytes: 0, recording }))
+}
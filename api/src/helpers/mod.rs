@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod helpers;
 pub use helpers::*;
 
@@ -8,4 +9,49 @@ pub mod schema;
 pub use schema::*;
 
 pub mod filters;
-pub use filters::*;
\ No newline at end of file
+pub use filters::*;
+
+pub mod params;
+pub use params::*;
+
+pub mod errors;
+pub use errors::*;
+
+pub mod store;
+pub use store::*;
+
+pub mod memstore;
+pub use memstore::*;
+
+pub mod config;
+pub use config::*;
+
+pub mod state;
+pub use state::*;
+
+pub mod datasets;
+pub use datasets::*;
+
+pub mod formats;
+pub use formats::*;
+
+pub mod netcdf;
+pub use netcdf::*;
+
+pub mod columnar;
+pub use columnar::*;
+
+pub mod pagination;
+pub use pagination::*;
+
+pub mod ratelimit;
+pub use ratelimit::*;
+
+pub mod cost;
+pub use cost::*;
+
+pub mod metrics;
+pub use metrics::*;
+
+pub mod telemetry;
+pub use telemetry::*;
\ No newline at end of file
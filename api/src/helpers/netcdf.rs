@@ -0,0 +1,169 @@
+use super::errors::ApiError;
+use super::helpers;
+use super::schema;
+
+use actix_web::HttpResponse;
+use netcdf3::{DataSet, FileWriter, Version, NC_FILL_F64};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{Cursor, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+// files are written with the netcdf3 crate in the 64-bit offset flavor of the classic format (CDF-2),
+// which libnetcdf, xarray and friends all read; no system netCDF library is needed to build or run the API.
+
+// the coordinate variables every file carries; data variables can't share their names
+pub const COORDINATE_VARIABLES: [&str; 5] = ["time", "level", "longitude", "latitude", "station_id"];
+
+// check before searching, so a clash is a 400 rather than a query wasted on a file that can't be written
+pub fn check_variable_names(data_info: &schema::DataInfo) -> Result<(), ApiError> {
+    let mut seen = HashSet::new();
+    for variable in &data_info.0 {
+        if COORDINATE_VARIABLES.contains(&variable.as_str()) {
+            return Err(ApiError::invalid("data", format!(
+                "'{}' in 'data' can't be written to netCDF, where it's the name of a coordinate variable; leave it out or choose another format",
+                variable,
+            )));
+        }
+        if !netcdf3::is_valid_name(variable) {
+            return Err(ApiError::invalid("data", format!("'{}' in 'data' isn't a valid netCDF variable name; choose another format", variable)));
+        }
+        if !seen.insert(variable) {
+            return Err(ApiError::invalid("data", format!("'{}' appears more than once in 'data', but a netCDF file can only hold it once", variable)));
+        }
+    }
+
+    Ok(())
+}
+
+// a CF timeSeries: one location per result, so station_id links each back to the record, and the /meta document,
+// it came from. results are single (longitude, latitude, level) cells, so level is a coordinate of each location
+// rather than a dimension of its own, which would leave every location empty at every level but one.
+// location is the record dimension, and data variables are laid out (location, time).
+// `timeseries` is used for results that were never sliced and so don't carry their own.
+#[tracing::instrument(name = "serialize", skip_all, fields(format = "netcdf"))]
+pub fn timeseries_netcdf<T: schema::IsTimeseries>(dataset: &str, mut results: Vec<T>, data_info: &schema::DataInfo, timeseries: &[String]) -> Result<HttpResponse, ApiError> {
+    check_variable_names(data_info)?;
+
+    // coordinate axes ///////////////////////////////////////////////
+    let record_times: Vec<Vec<String>> = results.iter_mut()
+        .map(|result| result.timeseries().map(|t| t.clone()).unwrap_or_else(|| timeseries.to_vec()))
+        .collect();
+    let times: Vec<String> = record_times.iter().flatten().cloned().collect::<BTreeSet<_>>().into_iter().collect();
+    let time_index: HashMap<&String, usize> = times.iter().enumerate().map(|(i, t)| (t, i)).collect();
+    let ids: Vec<String> = results.iter().map(|result| result._id()).collect();
+
+    // data, filled wherever a result has no value at a time, as when value constraints dropped it or it was null.
+    // the fill is netCDF's default rather than NaN, since netcdf3 can't match up a variable whose attributes hold a NaN
+    let (nx, nt) = (results.len(), times.len());
+    let mut values = vec![vec![NC_FILL_F64; nx * nt]; data_info.0.len()];
+    for (x, (result, record_times)) in results.iter_mut().zip(&record_times).enumerate() {
+        for (v, variable) in result.data().iter().enumerate().take(values.len()) {
+            for (i, time) in record_times.iter().enumerate() {
+                if let (Some(&t), Some(&value)) = (time_index.get(time), variable.get(i).filter(|value| !value.is_nan())) {
+                    values[v][x * nt + t] = value;
+                }
+            }
+        }
+    }
+
+    // file layout //////////////////////////////////////////////////
+    let id_length = ids.iter().map(String::len).max().unwrap_or(0).max(1);
+
+    let mut file = DataSet::new();
+    file.add_global_attr_string("Conventions", "CF-1.8")?;
+    file.add_global_attr_string("title", format!("Argovis {} timeseries", dataset))?;
+    file.add_global_attr_string("source", "Argovis API")?;
+    file.add_global_attr_string("featureType", "timeSeries")?;
+
+    file.set_unlimited_dim("location", nx)?;
+    file.add_fixed_dim("time", nt)?;
+    file.add_fixed_dim("id_length", id_length)?;
+
+    file.add_var_f64("time", &["time"])?;
+    file.add_var_attr_string("time", "standard_name", "time")?;
+    file.add_var_attr_string("time", "units", "seconds since 1970-01-01 00:00:00")?;
+    file.add_var_attr_string("time", "calendar", "standard")?;
+    file.add_var_attr_string("time", "axis", "T")?;
+
+    file.add_var_u8("station_id", &["location", "id_length"])?;
+    file.add_var_attr_string("station_id", "long_name", "_id of the record")?;
+    file.add_var_attr_string("station_id", "cf_role", "timeseries_id")?;
+
+    file.add_var_f64("level", &["location"])?;
+    file.add_var_attr_string("level", "long_name", "vertical level")?;
+    file.add_var_attr_string("level", "units", "m")?;
+    file.add_var_attr_string("level", "positive", "down")?;
+    file.add_var_attr_string("level", "axis", "Z")?;
+
+    file.add_var_f64("longitude", &["location"])?;
+    file.add_var_attr_string("longitude", "standard_name", "longitude")?;
+    file.add_var_attr_string("longitude", "units", "degrees_east")?;
+
+    file.add_var_f64("latitude", &["location"])?;
+    file.add_var_attr_string("latitude", "standard_name", "latitude")?;
+    file.add_var_attr_string("latitude", "units", "degrees_north")?;
+
+    for (v, variable) in data_info.0.iter().enumerate() {
+        file.add_var_f64(variable, &["location", "time"])?;
+        file.add_var_attr_f64(variable, "_FillValue", vec![NC_FILL_F64])?;
+        for (a, attribute) in data_info.1.iter().enumerate() {
+            if let Some(value) = data_info.2.get(v).and_then(|values| values.get(a)) {
+                file.add_var_attr_string(variable, attribute, value)?;
+            }
+        }
+        file.add_var_attr_string(variable, "coordinates", "time level longitude latitude station_id")?;
+    }
+
+    // values ///////////////////////////////////////////////////////
+    let seconds: Vec<f64> = times.iter()
+        .map(|t| helpers::string2bsondate(t).map(|d| d.timestamp_millis() as f64 / 1000.0).unwrap_or(f64::NAN))
+        .collect();
+    let station_ids: Vec<u8> = ids.iter()
+        .flat_map(|id| id.bytes().chain(std::iter::repeat(0)).take(id_length))
+        .collect();
+
+    let buffer = SharedBuffer::default();
+    let mut writer = FileWriter::open_seek_write(&format!("{}.nc", dataset), Box::new(buffer.clone()))?;
+    writer.set_def(&file, Version::Offset64Bit, 0)?;
+    writer.write_var_f64("time", &seconds)?;
+    writer.write_var_u8("station_id", &station_ids)?;
+    writer.write_var_f64("level", &results.iter().map(|result| result.level()).collect::<Vec<_>>())?;
+    writer.write_var_f64("longitude", &results.iter().map(|result| result.longitude()).collect::<Vec<_>>())?;
+    writer.write_var_f64("latitude", &results.iter().map(|result| result.latitude()).collect::<Vec<_>>())?;
+    for (variable, variable_values) in data_info.0.iter().zip(&values) {
+        writer.write_var_f64(variable, variable_values)?;
+    }
+    writer.close()?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-netcdf")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.nc\"", dataset)))
+        .body(buffer.into_bytes()))
+}
+
+// FileWriter takes ownership of its output, so the bytes are written through a handle kept here
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl SharedBuffer {
+    fn into_bytes(self) -> Vec<u8> {
+        self.0.take().into_inner()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl Seek for SharedBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
@@ -0,0 +1,134 @@
+use super::config::Config;
+use super::errors::ApiError;
+use super::params::SearchParams;
+use super::store::{DataStore, QueryOptions};
+
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::HttpResponse;
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, Bson, Document};
+
+// where the token for the following page is returned; absent on the last page
+pub const NEXT_PAGE_HEADER: &str = "x-next-page-token";
+
+// one page of a search: a filter matching exactly the documents on it, and the token for the page after it
+pub struct Page {
+    pub filter: Document,
+    pub next: Option<String>,
+}
+
+// pageSize if given, within the server's maximum; the configured default otherwise
+pub fn page_size(params: &SearchParams, config: &Config) -> Result<i64, ApiError> {
+    match params.page_size {
+        Some(page_size) if page_size > config.max_page_size => {
+            Err(ApiError::invalid("pageSize", format!("'pageSize' should be at most {}", config.max_page_size)))
+        },
+        Some(page_size) => Ok(page_size),
+        None => Ok(config.page_size),
+    }
+}
+
+// keyset pagination on _id, the sort key of every search: a page starts after the _id in pageToken.
+// only the _ids are read here, one past the page size, to tell whether another page follows;
+// the page itself is then read by _id range. that's two queries rather than one for page_size + 1 whole documents
+// because the next-page token goes out in a header, before the body: one query would hold up to max_page_size
+// documents in memory to find it, where this way they're streamed. tests/pagination.rs checks the range reads back
+// exactly the _ids paginated; a document inserted into that range between the two queries would join this page.
+// the legacy 'page' parameter still skips whole pages from the start.
+// None when nothing matches.
+#[tracing::instrument(name = "paginate", skip_all, fields(collection = %collection))]
+pub async fn page(store: &dyn DataStore, collection: &str, filter: Document, params: &SearchParams, config: &Config) -> Result<Option<Page>, ApiError> {
+    let page_size = page_size(params, config)?;
+    let skip = params.page.checked_mul(page_size)
+        .ok_or_else(|| ApiError::invalid("page", "'page' times 'pageSize' is too large; follow pageToken to reach later pages"))?;
+
+    let after = match &params.page_token {
+        Some(token) => doc! { "$and": [filter.clone(), { "_id": { "$gt": token } }] },
+        None => filter.clone(),
+    };
+    let options = QueryOptions {
+        sort: Some((String::from("_id"), 1)),
+        skip: Some(skip as u64),
+        limit: Some(page_size + 1),
+        projection: Some(doc! { "_id": 1 }),
+    };
+
+    let mut ids: Vec<Bson> = store.find(collection, after, options).await?
+        .map_ok(|document| document.get("_id").cloned().unwrap_or(Bson::Null))
+        .try_collect()
+        .await?;
+
+    let more = ids.len() as i64 > page_size;
+    ids.truncate(page_size as usize);
+
+    let (first, last) = match (ids.first(), ids.last()) {
+        (Some(first), Some(last)) => (first.clone(), last.clone()),
+        _ => return Ok(None),
+    };
+
+    let next = match (more, &last) {
+        (false, _) => None,
+        (true, Bson::String(last)) => Some(encode_token(last)),
+        (true, _) => return Err(ApiError::Internal(format!("can't paginate on non-string _id {}", last))),
+    };
+
+    Ok(Some(Page {
+        filter: doc! { "$and": [filter, { "_id": { "$gte": first, "$lte": last } }] },
+        next,
+    }))
+}
+
+// the legacy 'mostrecent' for point data: the latest documents by timestamp make up the only page.
+// None when nothing matches.
+#[tracing::instrument(name = "paginate", skip_all, fields(collection = %collection))]
+pub async fn most_recent(store: &dyn DataStore, collection: &str, filter: Document, most_recent: i64, config: &Config) -> Result<Option<Page>, ApiError> {
+    if most_recent > config.max_page_size {
+        return Err(ApiError::invalid("mostrecent", format!("'mostrecent' should be at most {}", config.max_page_size)));
+    }
+
+    let options = QueryOptions {
+        sort: Some((String::from("timestamp"), -1)),
+        limit: Some(most_recent),
+        projection: Some(doc! { "_id": 1 }),
+        ..QueryOptions::default()
+    };
+
+    let ids: Vec<Bson> = store.find(collection, filter.clone(), options).await?
+        .map_ok(|document| document.get("_id").cloned().unwrap_or(Bson::Null))
+        .try_collect()
+        .await?;
+
+    if ids.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(Page {
+        filter: doc! { "$and": [filter, { "_id": { "$in": ids } }] },
+        next: None,
+    }))
+}
+
+pub fn with_next_page(mut response: HttpResponse, next: Option<String>) -> HttpResponse {
+    if let Some(token) = next.and_then(|token| HeaderValue::from_str(&token).ok()) {
+        response.headers_mut().insert(HeaderName::from_static(NEXT_PAGE_HEADER), token);
+    }
+
+    response
+}
+
+// tokens are opaque to clients; today they're the hex-encoded last _id of the page before
+pub fn encode_token(id: &str) -> String {
+    id.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn decode_token(token: &str) -> Option<String> {
+    if !token.len().is_multiple_of(2) || !token.is_ascii() {
+        return None;
+    }
+
+    let bytes: Option<Vec<u8>> = (0..token.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&token[i..i + 2], 16).ok())
+        .collect();
+
+    String::from_utf8(bytes?).ok()
+}
@@ -0,0 +1,563 @@
+use chrono::DateTime;
+use mongodb::bson::DateTime as BsonDateTime;
+use serde_json::{from_str, json, Map, Value};
+use super::errors::Problem;
+use super::helpers;
+use super::pagination;
+use std::collections::HashSet;
+use utoipa::{IntoParams, ToSchema};
+
+pub const SEARCH_PARAMETERS: [&str; 17] = [
+    "id", "polygon", "box", "center", "radius", "verticalRange", "startDate", "endDate", "mostrecent",
+    "data", "compression", "batchmeta", "page", "pageSize", "pageToken", "format", "envelope",
+];
+
+// parameters /timeseries/{dataset}/meta understands; there, 'id' is a comma separated list of metadata _ids
+pub const META_PARAMETERS: [&str; 5] = ["id", "polygon", "box", "center", "radius"];
+
+// parameters whose valid values /timeseries/{dataset}/vocabulary lists
+pub const VOCABULARY_PARAMETERS: [&str; 5] = ["data", "basin", "level", "compression", "format"];
+
+pub const FORMATS: [&str; 7] = ["json", "csv", "netcdf", "geojson", "ndjson", "arrow", "parquet"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum Compression {
+    Minimal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+    Netcdf,
+    Geojson,
+    Ndjson,
+    Arrow,
+    Parquet,
+}
+
+impl Format {
+    // formats laying timeseries out as a table of (id, location, level, time) by variable
+    pub fn is_tabular(&self) -> bool {
+        matches!(self, Format::Csv | Format::Netcdf | Format::Arrow | Format::Parquet)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+// a bound on one data variable, from data=temperature,>10
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueConstraint {
+    pub variable: String,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+impl ValueConstraint {
+    // missing values (NaN) never satisfy a constraint
+    pub fn accepts(&self, x: f64) -> bool {
+        match self.comparison {
+            Comparison::Greater => x > self.value,
+            Comparison::GreaterOrEqual => x >= self.value,
+            Comparison::Less => x < self.value,
+            Comparison::LessOrEqual => x <= self.value,
+        }
+    }
+}
+
+// the search parameters as clients send them, for the openapi document; from_pairs does the parsing.
+// tests/openapi.rs checks these are exactly SEARCH_PARAMETERS, typed as from_pairs parses them
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// Return only the document with this _id
+    id: Option<String>,
+    /// A closed ring of [lon, lat] points to search within, like [[0,0],[10,0],[10,10],[0,0]]
+    polygon: Option<String>,
+    /// [[SW lon, SW lat], [NE lon, NE lat]] corners of a box to search within; may cross the dateline
+    #[param(rename = "box")]
+    boxregion: Option<String>,
+    /// [lon, lat] of the center of a circle to search within; needs radius
+    center: Option<String>,
+    /// Radius in meters of the circle around center
+    radius: Option<f64>,
+    /// [lower, upper) levels to keep, in meters
+    #[param(rename = "verticalRange")]
+    vertical_range: Option<String>,
+    /// Keep times at or after this, as YYYY-MM-DDTHH:MM:SSZ
+    #[param(rename = "startDate", format = DateTime)]
+    start_date: Option<String>,
+    /// Keep times before this, as YYYY-MM-DDTHH:MM:SSZ
+    #[param(rename = "endDate", format = DateTime)]
+    end_date: Option<String>,
+    /// Keep only this many of the latest timesteps (timeseries) or profiles (argo)
+    mostrecent: Option<i64>,
+    /// Comma separated variables to return, as listed by /timeseries/{dataset}/vocabulary?parameter=data, 'all', or 'except_data_values'; a bound after a variable, like temperature,>10, keeps only the values satisfying it
+    data: Option<String>,
+    /// Return [_id, longitude, latitude, level or timestamp, metadata] stubs instead of whole documents
+    compression: Option<Compression>,
+    /// Return the metadata documents the matching documents reference, instead of the documents; json only
+    batchmeta: Option<bool>,
+    /// Legacy: skip this many whole pages; prefer pageToken
+    page: Option<i64>,
+    /// Documents per page
+    #[param(rename = "pageSize")]
+    page_size: Option<i64>,
+    /// Resume after the page that returned this token in its x-next-page-token header
+    #[param(rename = "pageToken")]
+    page_token: Option<String>,
+    /// Response format; csv, netcdf, arrow and parquet are for timeseries only
+    format: Option<Format>,
+    /// Wrap json results as {next, query, data_info, timeseries, data, count}
+    envelope: Option<bool>,
+}
+
+// the metadata search parameters, for the openapi document; tests/openapi.rs checks these are exactly META_PARAMETERS, typed as parsed
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MetaQuery {
+    /// Comma separated _ids of the metadata documents to return
+    id: Option<String>,
+    /// A closed ring of [lon, lat] points; return the metadata of the grid cells within
+    polygon: Option<String>,
+    /// [[SW lon, SW lat], [NE lon, NE lat]] corners of a box; return the metadata of the grid cells within
+    #[param(rename = "box")]
+    boxregion: Option<String>,
+    /// [lon, lat] of the center of a circle; needs radius
+    center: Option<String>,
+    /// Radius in meters of the circle around center
+    radius: Option<f64>,
+}
+
+// the vocabulary parameters, for the openapi document
+#[allow(dead_code)]
+#[derive(IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VocabularyQuery {
+    /// The search parameter to list values for: data, basin, level, compression or format
+    parameter: String,
+}
+
+// query parameters accepted by /search, parsed and validated once at the edge of the handler
+#[derive(Debug, Clone, Default)]
+pub struct SearchParams {
+    pub id: Option<String>,
+    pub polygon: Option<Vec<Vec<f64>>>,   // single closed ring of [lon, lat] pairs
+    pub boxregion: Option<Vec<Vec<f64>>>, // [[lon, lat] of SW corner, [lon, lat] of NE corner]
+    pub center: Option<Vec<f64>>,         // [lon, lat]
+    pub radius: Option<f64>,              // meters
+    pub vertical_range: Option<Vec<f64>>, // [lower, upper)
+    pub start_date: Option<BsonDateTime>,
+    pub end_date: Option<BsonDateTime>,
+    pub most_recent: Option<i64>,         // timesteps for timeseries, documents for point data
+    pub data: Vec<String>,
+    pub data_constraints: Vec<ValueConstraint>,
+    pub compression: Option<Compression>,
+    pub batchmeta: bool,
+    pub page: i64,
+    pub page_size: Option<i64>,
+    pub page_token: Option<String>, // decoded; the _id the page starts after
+    pub format: Format,
+    pub envelope: bool,
+}
+
+impl SearchParams {
+    // every problem found is reported, rather than bailing out on the first one
+    #[tracing::instrument(name = "validate", skip_all)]
+    pub fn from_pairs(pairs: Vec<(String, String)>) -> Result<SearchParams, Vec<Problem>> {
+        let mut params = SearchParams::default();
+        let mut problems = Vec::new();
+        let mut seen = HashSet::new();
+
+        for (key, value) in pairs {
+            let Some(rule) = SEARCH_PARAMETERS.iter().copied().find(|parameter| *parameter == key) else {
+                problems.push(unrecognized(&key));
+                continue;
+            };
+            if !seen.insert(key.clone()) {
+                problems.push(Problem::new("duplicate_parameter", format!("'{}' should only be defined once", key)));
+                continue;
+            }
+
+            match rule {
+                "id" => params.id = Some(value),
+                "polygon" => params.polygon = collect(rule, parse_polygon(&value), &mut problems),
+                "box" => params.boxregion = collect(rule, parse_box(&value), &mut problems),
+                "center" => params.center = collect(rule, parse_center(&value), &mut problems),
+                "radius" => params.radius = collect(rule, parse_radius(&value), &mut problems),
+                "verticalRange" => params.vertical_range = collect(rule, parse_vertical_range(&value), &mut problems),
+                "startDate" => params.start_date = collect(rule, parse_date("startDate", &value), &mut problems),
+                "endDate" => params.end_date = collect(rule, parse_date("endDate", &value), &mut problems),
+                "mostrecent" => params.most_recent = collect(rule, parse_most_recent(&value), &mut problems),
+                "data" => {
+                    if let Some((data, constraints)) = collect(rule, parse_data(&value), &mut problems) {
+                        params.data = data;
+                        params.data_constraints = constraints;
+                    }
+                },
+                "compression" => params.compression = collect(rule, parse_compression(&value), &mut problems),
+                "batchmeta" => params.batchmeta = collect(rule, parse_flag("batchmeta", &value), &mut problems).unwrap_or_default(),
+                "envelope" => params.envelope = collect(rule, parse_flag("envelope", &value), &mut problems).unwrap_or_default(),
+                "page" => params.page = collect(rule, parse_page(&value), &mut problems).unwrap_or(0),
+                "pageSize" => params.page_size = collect(rule, parse_page_size(&value), &mut problems),
+                "pageToken" => params.page_token = collect(rule, parse_page_token(&value), &mut problems),
+                "format" => params.format = collect(rule, parse_format(&value), &mut problems).unwrap_or_default(),
+                // listed in SEARCH_PARAMETERS but not parsed above
+                _ => problems.push(unrecognized(&key)),
+            }
+        }
+
+        // should have at most one of polygon, box and center.
+        let regions = ["polygon", "box", "center"].iter().filter(|k| seen.contains(**k)).count();
+        if regions > 1 {
+            problems.push(Problem::new("region_conflict", "At most one of 'polygon', 'box', or 'center' should be defined"));
+        }
+
+        // 'startDate' should come strictly before 'endDate'
+        if let (Some(start_date), Some(end_date)) = (params.start_date, params.end_date) {
+            if start_date >= end_date {
+                problems.push(Problem::new("date_order", "'startDate' should be before 'endDate'"));
+            }
+        }
+
+        // file formats carry full data records, not stubs
+        if params.format.is_tabular() && params.compression.is_some() {
+            problems.push(Problem::new("format_conflict", "'format=csv', 'format=netcdf', 'format=arrow' and 'format=parquet' can't be combined with 'compression'"));
+        }
+
+        // metadata documents only come back as plain json
+        if params.format != Format::Json && params.batchmeta {
+            problems.push(Problem::new("format_conflict", "'batchmeta' is only available with 'format=json'"));
+        }
+
+        // the envelope wraps a json array
+        if params.format != Format::Json && params.envelope {
+            problems.push(Problem::new("format_conflict", "'envelope' is only available with 'format=json'"));
+        }
+
+        // 'page' counts from the start of the results, 'pageToken' from wherever the last page ended
+        if seen.contains("page") && seen.contains("pageToken") {
+            problems.push(Problem::new("pagination_conflict", "'page' and 'pageToken' can't both be defined"));
+        }
+
+        // 'center' and 'radius' should both be defined, or neither should be defined
+        if seen.contains("center") != seen.contains("radius") {
+            problems.push(Problem::new("center_radius", "'center' and 'radius' should both be defined, or neither should be defined"));
+        }
+
+        if problems.is_empty() {
+            Ok(params)
+        } else {
+            Err(problems)
+        }
+    }
+
+    // metadata searches take either a list of ids or a region, and none of the data parameters
+    pub fn from_meta_pairs(pairs: Vec<(String, String)>) -> Result<SearchParams, Vec<Problem>> {
+        let (pairs, unrecognized): (Vec<_>, Vec<_>) = pairs.into_iter().partition(|(key, _)| META_PARAMETERS.contains(&key.as_str()));
+        let mut problems: Vec<Problem> = unrecognized.iter()
+            .map(|(key, _)| Problem::new("unrecognized_parameter", format!("'{}' is not a recognized query parameter; valid parameters are {}", key, META_PARAMETERS.join(", "))))
+            .collect();
+
+        let params = SearchParams::from_pairs(pairs).map_err(|e| problems.extend(e)).ok();
+        if let Some(params) = &params {
+            let region = params.polygon.is_some() || params.boxregion.is_some() || params.center.is_some();
+            if params.id.is_some() == region {
+                problems.push(Problem::new("region_conflict", "Exactly one of 'id', 'polygon', 'box', or 'center' should be defined"));
+            }
+            if params.id.as_ref().is_some_and(|ids| ids.split(',').any(str::is_empty)) {
+                problems.push(Problem::new("id", "'id' should be a comma separated list of metadata ids, like id=a,b,c"));
+            }
+        }
+
+        match params {
+            Some(params) if problems.is_empty() => Ok(params),
+            _ => Err(problems),
+        }
+    }
+
+    // variables named in 'data' that `variables`, a dataset's data_info.0, doesn't have; slicing would quietly drop them
+    pub fn check_variables(&self, variables: &[String]) -> Result<(), Vec<Problem>> {
+        let problems: Vec<Problem> = self.data.iter()
+            .filter(|variable| *variable != "all" && *variable != "except_data_values" && !variables.contains(variable))
+            .map(|variable| Problem::new("data", format!("'{}' in 'data' isn't a variable of this dataset; valid variables are {}", variable, variables.join(", "))))
+            .collect();
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    // the parameters as the query sees them, for echoing back to clients; coordinates are after validlonlat
+    pub fn normalized(&self) -> Value {
+        let mut query = Map::new();
+        let mut set = |key: &str, value: Value| { query.insert(key.to_string(), value); };
+
+        if let Some(id) = &self.id {
+            set("id", json!(id));
+        }
+        if let Some(polygon) = &self.polygon {
+            set("polygon", json!(helpers::validlonlat(polygon.clone())));
+        }
+        if let Some(boxregion) = &self.boxregion {
+            set("box", json!(helpers::validlonlat(boxregion.clone())));
+        }
+        if let Some(center) = &self.center {
+            set("center", json!(helpers::validlonlat(vec![center.clone()])[0]));
+        }
+        if let Some(radius) = self.radius {
+            set("radius", json!(radius));
+        }
+        if let Some(vertical_range) = &self.vertical_range {
+            set("verticalRange", json!(vertical_range));
+        }
+        if let Some(start_date) = &self.start_date {
+            set("startDate", json!(helpers::bsondate2string(start_date)));
+        }
+        if let Some(end_date) = &self.end_date {
+            set("endDate", json!(helpers::bsondate2string(end_date)));
+        }
+        if let Some(most_recent) = self.most_recent {
+            set("mostrecent", json!(most_recent));
+        }
+        if !self.data.is_empty() {
+            set("data", json!(self.data));
+        }
+        if !self.data_constraints.is_empty() {
+            let constraints: Vec<Value> = self.data_constraints.iter().map(|c| {
+                let comparison = match c.comparison {
+                    Comparison::Greater => ">",
+                    Comparison::GreaterOrEqual => ">=",
+                    Comparison::Less => "<",
+                    Comparison::LessOrEqual => "<=",
+                };
+                json!([c.variable, comparison, c.value])
+            }).collect();
+            set("dataConstraints", json!(constraints));
+        }
+        if self.compression == Some(Compression::Minimal) {
+            set("compression", json!("minimal"));
+        }
+        if self.batchmeta {
+            set("batchmeta", json!(true));
+        }
+        if self.page > 0 {
+            set("page", json!(self.page));
+        }
+        if let Some(page_size) = self.page_size {
+            set("pageSize", json!(page_size));
+        }
+        if let Some(page_token) = &self.page_token {
+            set("pageToken", json!(pagination::encode_token(page_token)));
+        }
+
+        Value::Object(query)
+    }
+}
+
+// a query parameter the search routes don't take
+fn unrecognized(key: &str) -> Problem {
+    Problem::new("unrecognized_parameter", format!("'{}' is not a recognized query parameter; valid parameters are {}", key, SEARCH_PARAMETERS.join(", ")))
+}
+
+// the one 'parameter' /timeseries/{dataset}/vocabulary takes
+pub fn vocabulary_parameter(pairs: Vec<(String, String)>) -> Result<String, Vec<Problem>> {
+    let mut problems = Vec::new();
+    let mut parameters = Vec::new();
+    for (key, value) in pairs {
+        match key.as_str() {
+            "parameter" => parameters.push(value),
+            _ => problems.push(Problem::new("unrecognized_parameter", format!("'{}' is not a recognized query parameter; the only valid parameter is parameter", key))),
+        }
+    }
+
+    match parameters.as_slice() {
+        [parameter] if VOCABULARY_PARAMETERS.contains(&parameter.as_str()) => (),
+        [] | [_] => problems.push(Problem::new("parameter", format!("'parameter' should be one of {}", VOCABULARY_PARAMETERS.join(", ")))),
+        _ => problems.push(Problem::new("duplicate_parameter", "'parameter' should only be defined once")),
+    }
+
+    match problems.is_empty() {
+        true => Ok(parameters.remove(0)),
+        false => Err(problems),
+    }
+}
+
+// parsers say what's wrong with a value; the parameter they parse is the rule it broke
+fn collect<T>(rule: &'static str, parsed: Result<T, String>, problems: &mut Vec<Problem>) -> Option<T> {
+    parsed.map_err(|e| problems.push(Problem::new(rule, e))).ok()
+}
+
+fn parse_coordinate_list(name: &str, value: &str) -> Result<Vec<Vec<f64>>, String> {
+    let coordinates: Vec<Vec<f64>> = from_str(value).map_err(|_| format!("'{}' should be an array of coordinate pairs", name))?;
+
+    // Check that each point is a pair of coordinates
+    if coordinates.iter().any(|point| point.len() != 2) {
+        return Err(format!("Each point in '{}' should be a pair of coordinates", name));
+    }
+
+    Ok(coordinates)
+}
+
+fn parse_polygon(value: &str) -> Result<Vec<Vec<f64>>, String> {
+    let coordinates = parse_coordinate_list("polygon", value)?;
+
+    // Check that the polygon has at least 4 points (including the repeated start/end point)
+    if coordinates.len() < 4 {
+        return Err(String::from("'polygon' should have at least 4 points"));
+    }
+
+    // Check that the first and last points are the same
+    if coordinates[0] != coordinates[coordinates.len() - 1] {
+        return Err(String::from("'polygon' should be a closed ring"));
+    }
+
+    Ok(coordinates)
+}
+
+fn parse_box(value: &str) -> Result<Vec<Vec<f64>>, String> {
+    let coordinates = parse_coordinate_list("box", value)?;
+
+    if coordinates.len() != 2 {
+        return Err(String::from("'box' should be exactly two coordinate pairs, [[SW lon, SW lat], [NE lon, NE lat]]"));
+    }
+
+    Ok(coordinates)
+}
+
+fn parse_center(value: &str) -> Result<Vec<f64>, String> {
+    let center: Vec<f64> = from_str(value).map_err(|_| String::from("'center' should be a single coordinate pair, [lon, lat]"))?;
+
+    if center.len() != 2 {
+        return Err(String::from("'center' should be a single coordinate pair, [lon, lat]"));
+    }
+
+    Ok(center)
+}
+
+fn parse_radius(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(radius) if radius.is_finite() && radius >= 0.0 => Ok(radius),
+        _ => Err(String::from("'radius' should be a non-negative number of meters")),
+    }
+}
+
+fn parse_vertical_range(value: &str) -> Result<Vec<f64>, String> {
+    let range: Vec<f64> = from_str(value).map_err(|_| String::from("'verticalRange' should be a pair of numbers, [lower, upper]"))?;
+
+    if range.len() != 2 {
+        return Err(String::from("'verticalRange' should be a pair of numbers, [lower, upper]"));
+    }
+    if range[0] >= range[1] {
+        return Err(String::from("'verticalRange' should have its lower bound before its upper bound"));
+    }
+
+    Ok(range)
+}
+
+// If 'startDate' or 'endDate' are defined, they should have the format YYYY-MM-DDTHH:MM:SSZ
+fn parse_date(name: &str, value: &str) -> Result<BsonDateTime, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| BsonDateTime::from_millis(dt.timestamp_millis()))
+        .map_err(|_| format!("'{}' should have the format YYYY-MM-DDTHH:MM:SSZ", name))
+}
+
+fn parse_most_recent(value: &str) -> Result<i64, String> {
+    match value.parse::<i64>() {
+        Ok(most_recent) if most_recent > 0 => Ok(most_recent),
+        _ => Err(String::from("'mostrecent' should be a positive integer")),
+    }
+}
+
+// variable names, each optionally followed by bounds on its values: temperature,>10,<20,salinity,<35
+fn parse_data(value: &str) -> Result<(Vec<String>, Vec<ValueConstraint>), String> {
+    let mut data: Vec<String> = Vec::new();
+    let mut constraints = Vec::new();
+
+    for token in value.split(',') {
+        let (comparison, bound) = if let Some(bound) = token.strip_prefix(">=") {
+            (Comparison::GreaterOrEqual, bound)
+        } else if let Some(bound) = token.strip_prefix('>') {
+            (Comparison::Greater, bound)
+        } else if let Some(bound) = token.strip_prefix("<=") {
+            (Comparison::LessOrEqual, bound)
+        } else if let Some(bound) = token.strip_prefix('<') {
+            (Comparison::Less, bound)
+        } else if data.iter().any(|variable| variable == token) {
+            return Err(format!("'{}' is listed more than once in 'data'; put all its bounds after one mention, like {},>10,<20", token, token));
+        } else {
+            data.push(token.to_string());
+            continue;
+        };
+
+        let variable = match data.last() {
+            Some(variable) if variable != "all" && variable != "except_data_values" => variable.clone(),
+            _ => return Err(format!("'{}' in 'data' should follow the name of the variable it constrains", token)),
+        };
+        let value = bound.parse::<f64>().ok().filter(|v| v.is_finite())
+            .ok_or_else(|| format!("'{}' in 'data' should compare to a number, like >10", token))?;
+
+        constraints.push(ValueConstraint { variable, comparison, value });
+    }
+
+    Ok((data, constraints))
+}
+
+fn parse_compression(value: &str) -> Result<Compression, String> {
+    match value {
+        "minimal" => Ok(Compression::Minimal),
+        _ => Err(String::from("'compression' should be 'minimal' if defined")),
+    }
+}
+
+fn parse_format(value: &str) -> Result<Format, String> {
+    match value {
+        "json" => Ok(Format::Json),
+        "csv" => Ok(Format::Csv),
+        "netcdf" => Ok(Format::Netcdf),
+        "geojson" => Ok(Format::Geojson),
+        "ndjson" => Ok(Format::Ndjson),
+        "arrow" => Ok(Format::Arrow),
+        "parquet" => Ok(Format::Parquet),
+        _ => Err(format!("'format' should be one of {}", FORMATS.join(", "))),
+    }
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err(format!("'{}' should be true or false", name)),
+    }
+}
+
+fn parse_page(value: &str) -> Result<i64, String> {
+    match value.parse::<i64>() {
+        Ok(page) if page >= 0 => Ok(page),
+        _ => Err(String::from("'page' should be a non-negative integer")),
+    }
+}
+
+fn parse_page_size(value: &str) -> Result<i64, String> {
+    match value.parse::<i64>() {
+        Ok(page_size) if page_size > 0 => Ok(page_size),
+        _ => Err(String::from("'pageSize' should be a positive integer")),
+    }
+}
+
+fn parse_page_token(value: &str) -> Result<String, String> {
+    pagination::decode_token(value).ok_or_else(|| String::from("'pageToken' should be a token returned by a previous search"))
+}
@@ -0,0 +1,259 @@
+use super::config::{Config, RateLimit};
+use super::errors::ApiError;
+use super::state::AppState;
+use super::store::{DataStore, QueryOptions};
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use async_trait::async_trait;
+use futures::stream::TryStreamExt;
+use mongodb::bson::doc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// api keys ///////////////////////////////////////////////////////////////////
+
+// where api keys and their tiers are kept
+#[async_trait]
+pub trait KeyStore: Send + Sync {
+    // the tier of `key`, or None if it isn't a key we issued
+    async fn tier(&self, key: &str) -> Result<Option<String>, ApiError>;
+}
+
+// keys as {key, tier} documents in a collection of the data store
+pub struct CollectionKeys {
+    store: Arc<dyn DataStore>,
+    collection: String,
+}
+
+impl CollectionKeys {
+    pub fn new(store: Arc<dyn DataStore>, collection: &str) -> CollectionKeys {
+        CollectionKeys { store, collection: collection.to_string() }
+    }
+}
+
+#[async_trait]
+impl KeyStore for CollectionKeys {
+    async fn tier(&self, key: &str) -> Result<Option<String>, ApiError> {
+        let options = QueryOptions { limit: Some(1), ..QueryOptions::default() };
+        let document = self.store.find(&self.collection, doc! { "key": key }, options).await?.try_next().await?;
+
+        Ok(document.and_then(|document| document.get_str("tier").ok().map(String::from)))
+    }
+}
+
+// how long a key's tier, or its absence, is remembered before asking the store again
+pub const KEY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+// remembered lookups are swept of stale entries past this many, and dropped altogether if that isn't enough
+const KEY_CACHE_CAPACITY: usize = 10000;
+
+// remembers what another key store said, misses included, so repeated or bogus keys don't each cost a query
+pub struct CachedKeys<K> {
+    keys: K,
+    ttl: Duration,
+    tiers: Mutex<HashMap<String, (Option<String>, Instant)>>,
+}
+
+impl<K: KeyStore> CachedKeys<K> {
+    pub fn new(keys: K, ttl: Duration) -> CachedKeys<K> {
+        CachedKeys { keys, ttl, tiers: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl<K: KeyStore> KeyStore for CachedKeys<K> {
+    async fn tier(&self, key: &str) -> Result<Option<String>, ApiError> {
+        let now = Instant::now();
+        {
+            let tiers = self.tiers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some((tier, fetched)) = tiers.get(key) {
+                if now.saturating_duration_since(*fetched) < self.ttl {
+                    return Ok(tier.clone());
+                }
+            }
+        }
+
+        let tier = self.keys.tier(key).await?;
+
+        let mut tiers = self.tiers.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if tiers.len() >= KEY_CACHE_CAPACITY {
+            tiers.retain(|_, (_, fetched)| now.saturating_duration_since(*fetched) < self.ttl);
+            if tiers.len() >= KEY_CACHE_CAPACITY {
+                tiers.clear();
+            }
+        }
+        tiers.insert(key.to_string(), (tier.clone(), now));
+        Ok(tier)
+    }
+}
+
+// keys from a local json file of {"key": "tier", ...}, read once at startup
+#[derive(Debug, Clone, Default)]
+pub struct FileKeys {
+    keys: HashMap<String, String>,
+}
+
+impl FileKeys {
+    pub fn new(keys: HashMap<String, String>) -> FileKeys {
+        FileKeys { keys }
+    }
+
+    pub fn load(path: &str) -> std::io::Result<FileKeys> {
+        let keys = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(FileKeys::new(keys))
+    }
+}
+
+#[async_trait]
+impl KeyStore for FileKeys {
+    async fn tier(&self, key: &str) -> Result<Option<String>, ApiError> {
+        Ok(self.keys.get(key).cloned())
+    }
+}
+
+// token buckets //////////////////////////////////////////////////////////////
+
+// how often buckets idle long enough to have refilled are forgotten;
+// forgetting one only hands its owner the full bucket they'd have had anyway
+const BUCKET_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    limit: RateLimit,
+}
+
+impl Bucket {
+    fn refilled(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst)
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    pruned: Instant,
+}
+
+// one bucket per api key, and one per client address
+#[derive(Debug)]
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> RateLimiter {
+        RateLimiter::new()
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> RateLimiter {
+        RateLimiter { buckets: Mutex::new(Buckets { buckets: HashMap::new(), pruned: Instant::now() }) }
+    }
+
+    // take a token from `id`'s bucket, or say how long until one is available
+    pub fn take(&self, id: &str, limit: &RateLimit) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if now.saturating_duration_since(buckets.pruned) >= BUCKET_PRUNE_INTERVAL {
+            buckets.prune(now);
+        }
+
+        let bucket = buckets.buckets.entry(id.to_string()).or_insert(Bucket { tokens: limit.burst, updated: now, limit: *limit });
+        bucket.tokens = bucket.refilled(now);
+        bucket.updated = now;
+        bucket.limit = *limit;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / limit.per_second))
+        }
+    }
+
+    // forget every bucket that has refilled to its own burst
+    pub fn prune(&self) {
+        self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).prune(Instant::now());
+    }
+
+    // how many buckets are being kept
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Buckets {
+    fn prune(&mut self, now: Instant) {
+        self.buckets.retain(|_, bucket| bucket.refilled(now) < bucket.limit.burst);
+        self.pruned = now;
+    }
+}
+
+// middleware /////////////////////////////////////////////////////////////////
+
+// health and version probes from the orchestrator, metrics scrapes and the api docs are never limited
+pub const EXEMPT_PATHS: [&str; 5] = ["/healthz", "/readyz", "/version", "/metrics", "/openapi.json"];
+pub const EXEMPT_PREFIXES: [&str; 1] = ["/docs/"];
+
+fn tier_limit(config: &Config, tier: &str) -> Result<RateLimit, ApiError> {
+    config.rate_limits.get(tier).copied()
+        .ok_or_else(|| ApiError::Internal(format!("no rate limit is configured for tier '{}'", tier)))
+}
+
+fn take(state: &AppState, id: &str, limit: &RateLimit) -> Result<(), ApiError> {
+    state.limiter.take(id, limit)
+        .map_err(|wait| ApiError::RateLimited((wait.as_secs_f64().ceil() as u64).max(1)))
+}
+
+// every request draws first on its client address's bucket, so keys, valid or not, can't get around it,
+// and then on its key's bucket, or on the anonymous bucket for its address when it has no key
+async fn admit(req: &ServiceRequest) -> Result<(), ApiError> {
+    let state = req.app_data::<web::Data<AppState>>()
+        .ok_or_else(|| ApiError::Internal(String::from("rate limiting needs AppState in app data")))?;
+    let config: &Config = &state.config;
+
+    let address = {
+        let connection = req.connection_info();
+        let address = match config.trust_proxy_headers {
+            true => connection.realip_remote_addr(),
+            false => connection.peer_addr(),
+        };
+        address.unwrap_or("unknown").to_string()
+    };
+    take(state, &format!("ip:{}", address), &tier_limit(config, &config.address_tier)?)?;
+
+    match req.headers().get(config.api_key_header.as_str()).and_then(|key| key.to_str().ok()) {
+        Some(key) => {
+            let tier = state.keys.tier(key).await?
+                .ok_or_else(|| ApiError::Unauthorized(format!("The key given in '{}' is not a valid API key", config.api_key_header)))?;
+            take(state, &format!("key:{}", key), &tier_limit(config, &tier)?)
+        },
+        None => take(state, &format!("anonymous:{}", address), &tier_limit(config, &config.anonymous_tier)?),
+    }
+}
+
+// wrap the app in this with middleware::from_fn; needs AppState in app data.
+// rejected requests are answered here, as the same json errors handlers return
+pub async fn rate_limit(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if EXEMPT_PATHS.contains(&req.path()) || EXEMPT_PREFIXES.iter().any(|prefix| req.path().starts_with(prefix)) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    match admit(&req).await {
+        Ok(()) => Ok(next.call(req).await?.map_into_left_body()),
+        Err(e) => Ok(req.error_response(e).map_into_right_body()),
+    }
+}
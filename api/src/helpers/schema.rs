@@ -1,22 +1,89 @@
-use serde::{Deserialize, Serialize};
+use super::helpers;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde::ser::{Serializer, SerializeSeq};
 use mongodb::bson::DateTime as BsonDateTime;
+use serde_json::json;
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
 
 // generic structs ////////////////////////////////////////////////////////////
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct GeoJSONPoint {
     #[serde(rename = "type")]
     location_type: String,
     coordinates: [f64; 2],
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct SourceMeta { 
     source: Vec<String>,
     file: String
 }
 
+// variable names, per-variable attribute names, and per-variable attribute values
+pub type DataInfo = (Vec<String>, Vec<String>, Vec<Vec<String>>);
+
+// missing values are stored as null; carry them as NaN, which serde_json writes back out as null
+fn nulls_as_nan<'de, D>(deserializer: D) -> Result<Vec<Vec<f64>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let data: Vec<Vec<Option<f64>>> = Deserialize::deserialize(deserializer)?;
+    Ok(data.into_iter().map(|row| row.into_iter().map(|x| x.unwrap_or(f64::NAN)).collect()).collect())
+}
+
+fn bsondate_as_string<S>(date: &BsonDateTime, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&helpers::bsondate2string(date))
+}
+
+// openapi ////////////////////////////////////////////////////////////////////
+
+// schemas for the shapes serde writes that utoipa can't derive: tuples, stubs serialized as arrays, and extended json dates
+fn json_schema(schema: serde_json::Value) -> RefOr<Schema> {
+    RefOr::T(serde_json::from_value(schema).expect("a valid schema literal"))
+}
+
+pub(crate) fn data_info_schema() -> RefOr<Schema> {
+    json_schema(json!({
+        "type": "array",
+        "description": "[variable names, attribute names, attribute values per variable], like [[\"temperature\"], [\"units\"], [[\"degC\"]]]",
+        "prefixItems": [
+            {"type": "array", "items": {"type": "string"}},
+            {"type": "array", "items": {"type": "string"}},
+            {"type": "array", "items": {"type": "array", "items": {"type": "string"}}},
+        ],
+        "items": false,
+    }))
+}
+
+fn optional_data_info_schema() -> RefOr<Schema> {
+    json_schema(json!({"oneOf": [{"type": "null"}, serde_json::to_value(data_info_schema()).unwrap()]}))
+}
+
+fn data_schema() -> RefOr<Schema> {
+    json_schema(json!({
+        "type": "array",
+        "description": "one array of values per variable in data_info; missing values are null",
+        "items": {"type": "array", "items": {"type": ["number", "null"]}},
+    }))
+}
+
+fn extended_json_date_schema() -> RefOr<Schema> {
+    json_schema(json!({
+        "type": "object",
+        "description": "milliseconds since 1970, as MongoDB extended json",
+        "properties": {"$date": {"type": "object", "properties": {"$numberLong": {"type": "string"}}}},
+    }))
+}
+
+fn extended_json_dates_schema() -> RefOr<Schema> {
+    json_schema(json!({"type": "array", "items": serde_json::to_value(extended_json_date_schema()).unwrap()}))
+}
+
 // categroical traits /////////////////////////////////////////////////////////
 
 pub trait IsTimeseries {
@@ -25,7 +92,7 @@ pub trait IsTimeseries {
     fn set_data(&mut self, data: Vec<Vec<f64>>);
     fn timeseries(&mut self) -> Option<&mut Vec<String>>;
     fn set_timeseries(&mut self, timeseries: Vec<String>);
-    fn set_data_info(&mut self, data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>));
+    fn set_data_info(&mut self, data_info: DataInfo);
     fn _id(&self) -> String;
     fn longitude(&self) -> f64;
     fn latitude(&self) -> f64;
@@ -37,9 +104,23 @@ pub trait IsTimeseriesMeta {
     fn get_timeseries_meta(&self) -> bool;
 }
 
+// one document per place and time, each carrying its own data_info
+pub trait IsPointData {
+    fn get_point_data(&self) -> bool;
+    fn data(&mut self) -> &mut Vec<Vec<f64>>;
+    fn set_data(&mut self, data: Vec<Vec<f64>>);
+    fn data_info(&self) -> DataInfo;
+    fn set_data_info(&mut self, data_info: DataInfo);
+    fn _id(&self) -> String;
+    fn longitude(&self) -> f64;
+    fn latitude(&self) -> f64;
+    fn timestamp(&self) -> BsonDateTime;
+    fn metadata(&self) -> Vec<String>;
+}
+
 // bsose //////////////////////////////////////////////////////////////////////
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct BsoseSchema {
     _id: String,
     pub metadata: Vec<String>,
@@ -51,14 +132,16 @@ pub struct BsoseSchema {
     ctrl_vector_3d_mask: bool,
     cell_z_size: f64,
     reference_density_profile: f64,
+    #[schema(schema_with = data_schema)]
     data: Vec<Vec<f64>>,
     timeseries: Option<Vec<String>>, // since this field isnt present in the data collection, but gets munged on later
-    data_info: Option<(Vec<String>, Vec<String>, Vec<Vec<String>>)>,
+    #[schema(schema_with = optional_data_info_schema)]
+    data_info: Option<DataInfo>,
 }
 
 impl IsTimeseries for BsoseSchema {
     fn get_timeseries(&self) -> bool {
-        return true;
+        true
     }
 
     fn data(&mut self) -> &mut Vec<Vec<f64>> {
@@ -77,7 +160,7 @@ impl IsTimeseries for BsoseSchema {
         self.timeseries = Some(timeseries);
     }
 
-    fn set_data_info(&mut self, data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>)) {
+    fn set_data_info(&mut self, data_info: DataInfo) {
         self.data_info = Some(data_info);
     }
 
@@ -102,12 +185,15 @@ impl IsTimeseries for BsoseSchema {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct BsoseMeta { 
     _id: String,
     data_type: String,
-    pub data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>),
+    #[schema(schema_with = data_info_schema)]
+    pub data_info: DataInfo,
+    #[schema(schema_with = extended_json_date_schema)]
     date_updated_argovis: BsonDateTime,
+    #[schema(schema_with = extended_json_dates_schema)]
     pub timeseries: Vec<BsonDateTime>,
     source: Vec<SourceMeta>,
     cell_area: f64,
@@ -119,7 +205,158 @@ pub struct BsoseMeta {
 
 impl IsTimeseriesMeta for BsoseMeta {
     fn get_timeseries_meta(&self) -> bool {
-        return true;
+        true
+    }
+}
+
+// generic gridded timeseries: noaasst, copernicussla, ccmpwind ///////////////
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GridSchema {
+    _id: String,
+    pub metadata: Vec<String>,
+    basin: f64,
+    geolocation: GeoJSONPoint,
+    #[serde(default)]
+    level: f64, // surface products don't record a level
+    #[serde(deserialize_with = "nulls_as_nan")]
+    #[schema(schema_with = data_schema)]
+    data: Vec<Vec<f64>>,
+    timeseries: Option<Vec<String>>,
+    #[schema(schema_with = optional_data_info_schema)]
+    data_info: Option<DataInfo>,
+}
+
+impl IsTimeseries for GridSchema {
+    fn get_timeseries(&self) -> bool {
+        true
+    }
+
+    fn data(&mut self) -> &mut Vec<Vec<f64>> {
+        &mut self.data
+    }
+
+    fn set_data(&mut self, data: Vec<Vec<f64>>) {
+        self.data = data;
+    }
+
+    fn timeseries(&mut self) -> Option<&mut Vec<String>> {
+        self.timeseries.as_mut()
+    }
+
+    fn set_timeseries(&mut self, timeseries: Vec<String>) {
+        self.timeseries = Some(timeseries);
+    }
+
+    fn set_data_info(&mut self, data_info: DataInfo) {
+        self.data_info = Some(data_info);
+    }
+
+    fn _id(&self) -> String {
+        self._id.clone()
+    }
+
+    fn longitude(&self) -> f64 {
+        self.geolocation.coordinates[0]
+    }
+
+    fn latitude(&self) -> f64 {
+        self.geolocation.coordinates[1]
+    }
+
+    fn level(&self) -> f64 {
+        self.level
+    }
+
+    fn metadata(&self) -> Vec<String> {
+        self.metadata.clone()
+    }
+}
+
+// the fields every timeseries metadata document shares, BSOSE included
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct GridMeta {
+    _id: String,
+    pub data_type: String,
+    #[schema(schema_with = data_info_schema)]
+    pub data_info: DataInfo,
+    #[schema(schema_with = extended_json_date_schema)]
+    pub date_updated_argovis: BsonDateTime,
+    #[schema(schema_with = extended_json_dates_schema)]
+    pub timeseries: Vec<BsonDateTime>,
+    pub source: Vec<SourceMeta>,
+}
+
+impl IsTimeseriesMeta for GridMeta {
+    fn get_timeseries_meta(&self) -> bool {
+        true
+    }
+}
+
+// argo ///////////////////////////////////////////////////////////////////////
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct ProfileSchema {
+    _id: String,
+    pub metadata: Vec<String>,
+    basin: f64,
+    geolocation: GeoJSONPoint,
+    geolocation_argoqc: i32,
+    #[serde(serialize_with = "bsondate_as_string")]
+    #[schema(value_type = String, format = DateTime)]
+    timestamp: BsonDateTime,
+    timestamp_argoqc: i32,
+    #[serde(default)]
+    platform_number: Option<String>,
+    cycle_number: i64,
+    #[serde(default)]
+    profile_direction: Option<String>,
+    #[serde(deserialize_with = "nulls_as_nan")]
+    #[schema(schema_with = data_schema)]
+    data: Vec<Vec<f64>>, // data[variable][level], variables named in data_info.0
+    #[schema(schema_with = data_info_schema)]
+    data_info: DataInfo,
+}
+
+impl IsPointData for ProfileSchema {
+    fn get_point_data(&self) -> bool {
+        true
+    }
+
+    fn data(&mut self) -> &mut Vec<Vec<f64>> {
+        &mut self.data
+    }
+
+    fn set_data(&mut self, data: Vec<Vec<f64>>) {
+        self.data = data;
+    }
+
+    fn data_info(&self) -> DataInfo {
+        self.data_info.clone()
+    }
+
+    fn set_data_info(&mut self, data_info: DataInfo) {
+        self.data_info = data_info;
+    }
+
+    fn _id(&self) -> String {
+        self._id.clone()
+    }
+
+    fn longitude(&self) -> f64 {
+        self.geolocation.coordinates[0]
+    }
+
+    fn latitude(&self) -> f64 {
+        self.geolocation.coordinates[1]
+    }
+
+    fn timestamp(&self) -> BsonDateTime {
+        self.timestamp
+    }
+
+    fn metadata(&self) -> Vec<String> {
+        self.metadata.clone()
     }
 }
 
@@ -145,4 +382,66 @@ impl Serialize for TimeseriesStub {
         seq.serialize_element(&self.metadata)?;
         seq.end()
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone)]
+pub struct PointStub {
+    pub _id: String,
+    pub longitude: f64,
+    pub latitude: f64,
+    pub timestamp: BsonDateTime,
+    pub metadata: Vec<String>,
+}
+
+impl Serialize for PointStub {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(5))?;
+        seq.serialize_element(&self._id)?;
+        seq.serialize_element(&self.longitude)?;
+        seq.serialize_element(&self.latitude)?;
+        seq.serialize_element(&helpers::bsondate2string(&self.timestamp))?;
+        seq.serialize_element(&self.metadata)?;
+        seq.end()
+    }
+}
+
+impl PartialSchema for TimeseriesStub {
+    fn schema() -> RefOr<Schema> {
+        json_schema(json!({
+            "type": "array",
+            "description": "[_id, longitude, latitude, level, metadata]",
+            "prefixItems": [
+                {"type": "string"},
+                {"type": "number"},
+                {"type": "number"},
+                {"type": "number"},
+                {"type": "array", "items": {"type": "string"}},
+            ],
+            "items": false,
+        }))
+    }
+}
+
+impl ToSchema for TimeseriesStub {}
+
+impl PartialSchema for PointStub {
+    fn schema() -> RefOr<Schema> {
+        json_schema(json!({
+            "type": "array",
+            "description": "[_id, longitude, latitude, timestamp, metadata]",
+            "prefixItems": [
+                {"type": "string"},
+                {"type": "number"},
+                {"type": "number"},
+                {"type": "string", "format": "date-time"},
+                {"type": "array", "items": {"type": "string"}},
+            ],
+            "items": false,
+        }))
+    }
+}
+
+impl ToSchema for PointStub {}
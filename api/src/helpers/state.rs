@@ -0,0 +1,144 @@
+use super::config::Config;
+use super::datasets::{self, Dataset, DatasetSpec, Vocabulary};
+use super::errors::ApiError;
+use super::metrics::{MeteredStore, Metrics};
+use super::ratelimit::{self, CachedKeys, CollectionKeys, KeyStore, RateLimiter};
+use super::schema;
+use super::store::{self, DataStore};
+
+use mongodb::bson::doc;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// how long load_datasets waits before retrying, doubling after each attempt up to the max
+const LOAD_RETRY_FIRST: Duration = Duration::from_secs(1);
+const LOAD_RETRY_MAX: Duration = Duration::from_secs(60);
+
+// everything handlers share, injected with web::Data
+pub struct AppState {
+    pub store: Arc<dyn DataStore>,
+    pub config: Config,
+    pub datasets: RwLock<HashMap<String, Arc<Dataset>>>, // those of config.datasets loaded so far
+    pub vocabularies: RwLock<HashMap<String, Arc<Vocabulary>>>, // by dataset name, for those loaded so far
+    pub keys: Arc<dyn KeyStore>,
+    pub limiter: RateLimiter,
+    pub metrics: Arc<Metrics>,
+}
+
+impl AppState {
+    // api keys are looked up in config.api_keys_collection, and remembered for a while, unless replaced with with_keys.
+    // queries through `store` are timed into `metrics`
+    pub fn new(store: Arc<dyn DataStore>, config: Config) -> AppState {
+        let metrics = Arc::new(Metrics::new());
+        let store: Arc<dyn DataStore> = Arc::new(MeteredStore::new(store, metrics.clone()));
+        let keys = Arc::new(CachedKeys::new(CollectionKeys::new(store.clone(), &config.api_keys_collection), ratelimit::KEY_CACHE_TTL));
+        AppState { store, config, datasets: RwLock::new(HashMap::new()), vocabularies: RwLock::new(HashMap::new()), keys, limiter: RateLimiter::new(), metrics }
+    }
+
+    pub fn with_keys(mut self, keys: Arc<dyn KeyStore>) -> AppState {
+        self.keys = keys;
+        self
+    }
+
+    // fetch a dataset's timeseries and data_info from its metadata collection, and serve it under spec.name
+    pub async fn register(&self, spec: DatasetSpec) -> Result<(), ApiError> {
+        let metadata = self.store.dataset_metadata(&spec.metadata_collection, &spec.data_type).await?
+            .ok_or_else(|| ApiError::NotFound(format!("no {} metadata found in {}", spec.data_type, spec.metadata_collection)))?;
+        let metadata = store::from_document::<schema::GridMeta>(metadata)?;
+        let document_count = self.store.estimated_count(&spec.collection).await?;
+
+        let dataset = Dataset {
+            spec,
+            timeseries: metadata.timeseries,
+            data_info: metadata.data_info,
+            document_count,
+            date_updated: metadata.date_updated_argovis,
+            source: metadata.source,
+        };
+        self.write_datasets().insert(dataset.spec.name.clone(), Arc::new(dataset));
+
+        Ok(())
+    }
+
+    // fetch the basins and levels a dataset's data documents cover, for its /vocabulary
+    pub async fn load_vocabulary(&self, spec: &DatasetSpec) -> Result<(), ApiError> {
+        let basins = datasets::sorted_values(self.store.distinct(&spec.collection, "basin", doc! {}).await?);
+        let levels = datasets::sorted_values(self.store.distinct(&spec.collection, "level", doc! {}).await?);
+        self.write_vocabularies().insert(spec.name.clone(), Arc::new(Vocabulary { basins, levels }));
+
+        Ok(())
+    }
+
+    // register every dataset in config.datasets, then load its vocabulary, retrying with backoff until all of them are loaded;
+    // spawn this at startup, so the server can come up (and report itself not ready) while the database can't be reached.
+    // a dataset is searchable once registered, whether or not its vocabulary has loaded
+    pub async fn load_datasets(&self) {
+        let mut wait = LOAD_RETRY_FIRST;
+        loop {
+            let mut pending = 0;
+            for spec in self.config.datasets.iter() {
+                if !self.is_loaded(&spec.name) {
+                    if let Err(e) = self.register(spec.clone()).await {
+                        tracing::warn!(dataset = %spec.name, retry_secs = wait.as_secs(), "can't load dataset: {}", e);
+                        pending += 1;
+                        continue;
+                    }
+                }
+                if !self.read_vocabularies().contains_key(&spec.name) {
+                    if let Err(e) = self.load_vocabulary(spec).await {
+                        tracing::warn!(dataset = %spec.name, retry_secs = wait.as_secs(), "can't load vocabulary: {}", e);
+                        pending += 1;
+                    }
+                }
+            }
+            if pending == 0 {
+                return;
+            }
+
+            actix_web::rt::time::sleep(wait).await;
+            wait = (wait * 2).min(LOAD_RETRY_MAX);
+        }
+    }
+
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.read_datasets().contains_key(name)
+    }
+
+    pub fn dataset(&self, name: &str) -> Result<Arc<Dataset>, ApiError> {
+        if let Some(dataset) = self.read_datasets().get(name) {
+            return Ok(dataset.clone());
+        }
+
+        if self.config.datasets.iter().any(|spec| spec.name == name) {
+            return Err(ApiError::Unavailable(format!("Dataset '{}' is still loading; try again shortly", name)));
+        }
+
+        let mut available: Vec<&str> = self.config.datasets.iter().map(|spec| spec.name.as_str()).collect();
+        available.sort();
+        Err(ApiError::NotFound(format!("No timeseries dataset named '{}'; available datasets are {}", name, available.join(", "))))
+    }
+
+    // the basins and levels of a dataset known to be loaded
+    pub fn vocabulary(&self, name: &str) -> Result<Arc<Vocabulary>, ApiError> {
+        self.read_vocabularies().get(name).cloned()
+            .ok_or_else(|| ApiError::Unavailable(format!("Basins and levels of '{}' are still loading; try again shortly", name)))
+    }
+
+    // inserts are all-or-nothing, so a poisoned lock still guards a consistent map
+    fn read_datasets(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Arc<Dataset>>> {
+        self.datasets.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_datasets(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Arc<Dataset>>> {
+        self.datasets.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn read_vocabularies(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, Arc<Vocabulary>>> {
+        self.vocabularies.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_vocabularies(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Arc<Vocabulary>>> {
+        self.vocabularies.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
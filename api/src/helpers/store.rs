@@ -0,0 +1,103 @@
+use super::errors::ApiError;
+
+use async_trait::async_trait;
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::options::{DistinctOptions, FindOptions};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+pub type DocumentStream = BoxStream<'static, Result<Document, ApiError>>;
+
+#[derive(Debug, Clone, Default)]
+pub struct QueryOptions {
+    pub sort: Option<(String, i32)>, // (field, 1 for ascending or -1 for descending)
+    pub skip: Option<u64>,
+    pub limit: Option<i64>,
+    pub projection: Option<Document>, // fields to include, as {field: 1}
+}
+
+// everything the handlers need from the database; filters are the same documents filters.rs builds for MongoDB
+#[async_trait]
+pub trait DataStore: Send + Sync {
+    // documents in `collection` matching `filter`, streamed as they arrive
+    async fn find(&self, collection: &str, filter: Document, options: QueryOptions) -> Result<DocumentStream, ApiError>;
+
+    // a quick count of every document in `collection`, from collection metadata rather than a scan
+    async fn estimated_count(&self, collection: &str) -> Result<u64, ApiError>;
+
+    // whether the store can be reached at all
+    async fn ping(&self) -> Result<(), ApiError>;
+
+    // the distinct values of `field` among documents matching `filter`; arrays contribute each of their elements
+    async fn distinct(&self, collection: &str, field: &str, filter: Document) -> Result<Vec<Bson>, ApiError>;
+
+    // metadata documents referenced by a set of data documents
+    async fn find_metadata(&self, collection: &str, ids: Vec<String>) -> Result<Vec<Document>, ApiError> {
+        let filter = doc! { "_id": { "$in": ids } };
+        self.find(collection, filter, QueryOptions::default()).await?.try_collect().await
+    }
+
+    // the metadata document describing a whole dataset, like the one carrying timeseries and data_info for BSOSE
+    async fn dataset_metadata(&self, collection: &str, data_type: &str) -> Result<Option<Document>, ApiError> {
+        let filter = doc! { "data_type": data_type };
+        let options = QueryOptions { limit: Some(1), ..QueryOptions::default() };
+        self.find(collection, filter, options).await?.try_next().await
+    }
+}
+
+pub fn from_document<T: DeserializeOwned>(document: Document) -> Result<T, ApiError> {
+    Ok(bson::from_document(document)?)
+}
+
+// mongodb ////////////////////////////////////////////////////////////////////
+
+pub struct MongoStore {
+    client: mongodb::Client,
+    database: String,
+    query_timeout: Option<Duration>,
+}
+
+impl MongoStore {
+    pub fn new(client: mongodb::Client, database: &str) -> MongoStore {
+        MongoStore { client, database: database.to_string(), query_timeout: None }
+    }
+
+    // queries running longer than this on the server are abandoned
+    pub fn with_query_timeout(mut self, timeout: Duration) -> MongoStore {
+        self.query_timeout = Some(timeout);
+        self
+    }
+}
+
+#[async_trait]
+impl DataStore for MongoStore {
+    #[tracing::instrument(name = "mongo.find", skip_all, fields(collection = %collection))]
+    async fn find(&self, collection: &str, filter: Document, options: QueryOptions) -> Result<DocumentStream, ApiError> {
+        let find_options = FindOptions::builder()
+            .sort(options.sort.map(|(field, direction)| doc! { field: direction }))
+            .skip(options.skip)
+            .limit(options.limit)
+            .projection(options.projection)
+            .max_time(self.query_timeout)
+            .build();
+
+        let cursor = self.client.database(&self.database).collection::<Document>(collection).find(filter, find_options).await?;
+        Ok(cursor.map_err(ApiError::from).boxed())
+    }
+
+    async fn estimated_count(&self, collection: &str) -> Result<u64, ApiError> {
+        Ok(self.client.database(&self.database).collection::<Document>(collection).estimated_document_count(None).await?)
+    }
+
+    #[tracing::instrument(name = "mongo.distinct", skip_all, fields(collection = %collection, field = %field))]
+    async fn distinct(&self, collection: &str, field: &str, filter: Document) -> Result<Vec<Bson>, ApiError> {
+        let options = DistinctOptions::builder().max_time(self.query_timeout).build();
+        Ok(self.client.database(&self.database).collection::<Document>(collection).distinct(field, filter, options).await?)
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.client.database(&self.database).run_command(doc! { "ping": 1 }, None).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use super::config::{Config, LogFormat};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use futures::stream::{self, BoxStream, StreamExt};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+// echoed on every response; taken from the request when a proxy in front of us already set one
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// keeps trace export running; dropping it at shutdown flushes spans not yet sent
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("couldn't flush traces: {}", e);
+            }
+        }
+    }
+}
+
+// log to stdout as config.log_format, and export spans over OTLP/HTTP when config.otlp_endpoint is set.
+// call before starting the async runtime; the exporter's http client runs its own
+pub fn init(config: &Config) -> Result<Telemetry, String> {
+    let filter = EnvFilter::try_new(&config.log_filter).map_err(|e| format!("log_filter isn't valid: {}", e))?;
+
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+                .map_err(|e| format!("can't export traces to {}: {}", endpoint, e))?;
+            let resource = Resource::builder().with_service_name(config.service_name.clone()).build();
+            Some(SdkTracerProvider::builder().with_batch_exporter(exporter).with_resource(resource).build())
+        },
+        None => None,
+    };
+    let spans = provider.as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(config.service_name.clone())));
+
+    let logs = match config.log_format {
+        LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(spans)
+        .with(logs)
+        .try_init()
+        .map_err(|e| e.to_string())?;
+
+    Ok(Telemetry { provider })
+}
+
+// poll `stream` inside `span`, for work that happens while a response body is written, after the handler has returned
+pub fn in_span<T: Send + 'static>(mut stream: BoxStream<'static, T>, span: Span) -> BoxStream<'static, T> {
+    stream::poll_fn(move |cx| {
+        let _entered = span.enter();
+        stream.poll_next_unpin(cx)
+    }).boxed()
+}
+
+fn request_id(req: &ServiceRequest) -> String {
+    req.headers().get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 128 && id.chars().all(|c| c.is_ascii_graphic()))
+        .map(String::from)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+// wrap the app in this with middleware::from_fn: everything logged while handling a request carries its id
+pub async fn trace_request(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let start = Instant::now();
+    let request_id = request_id(&req);
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        route = req.match_pattern().unwrap_or_default(),
+        path = req.path(),
+        status = Empty,
+    );
+
+    let mut response = next.call(req).instrument(span.clone()).await?;
+
+    let status = response.status().as_u16();
+    span.record("status", status);
+    span.in_scope(|| tracing::info!(status, elapsed_ms = start.elapsed().as_millis() as u64, "request handled"));
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+    }
+
+    Ok(response)
+}
@@ -1,128 +1,313 @@
 use super::schema;
 use super::helpers;
+use super::params::{SearchParams, ValueConstraint};
+use super::errors::ApiError;
 use mongodb::bson::DateTime as BsonDateTime;
+use std::ops::Range;
 
-pub fn transform_timeseries<T: schema::IsTimeseries + Clone>(params: serde_json::Value, ts: Vec<BsonDateTime>, data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>), results: Vec<T>) -> Vec<T> {
-    
-    // extract query parameters //////////////////////////////////////
-    let start_date = params.get("startDate")
-        .and_then(|v| v.as_str())
-        .and_then(helpers::string2bsondate);
-
-    let end_date = params.get("endDate")
-        .and_then(|v| v.as_str())
-        .and_then(helpers::string2bsondate);
+pub fn transform_timeseries<T: schema::IsTimeseries>(params: &SearchParams, ts: &[BsonDateTime], data_info: &schema::DataInfo, results: Vec<T>) -> Result<Vec<T>, ApiError> {
+    results.into_iter()
+        .filter_map(|result| transform_timeseries_record(params, ts, data_info, result).transpose())
+        .collect()
+}
 
-    let data: Vec<String> = params.get("data")
-        .and_then(|v| v.as_str())
-        .map(|s| s.split(',').map(|s| s.to_string()).collect())
-        .unwrap_or_else(Vec::new);
+// the same transforms, one document at a time, so results can be streamed straight off the cursor;
+// None when the document has nothing left to return
+#[tracing::instrument(name = "transform", level = "debug", skip_all)]
+pub fn transform_timeseries_record<T: schema::IsTimeseries>(params: &SearchParams, ts: &[BsonDateTime], data_info: &schema::DataInfo, mut result: T) -> Result<Option<T>, ApiError> {
 
     // apply appropriate transforms ////////////////////////////////////
-    let mut r = results.clone();
-
-    if start_date.is_some() || end_date.is_some() {
-        r = slice_timerange(start_date, end_date, ts, r);
+    if params.start_date.is_some() || params.end_date.is_some() || params.most_recent.is_some() {
+        let window = selected_window(params, ts)?;
+        if window.is_empty() {
+            return Ok(None);
+        }
+        slice_timerange(&window, ts, &mut result)?;
+    }
+    if !params.data_constraints.is_empty() && !filter_timeseries_values(&params.data_constraints, ts, data_info, &mut result) {
+        return Ok(None);
+    }
+    if !slice_data(&params.data, data_info, &mut result) {
+        return Ok(None);
     }
-    r = slice_data(data, data_info, r);
 
-    return r;
+    Ok(Some(result))
 }
 
-pub fn slice_timerange<T: schema::IsTimeseries>(start_date: Option<BsonDateTime>, end_date: Option<BsonDateTime>, ts: Vec<BsonDateTime>, mut results: Vec<T>) -> Vec<T> {
+// indexes of the timesteps in [start_date, end_date); an error if the window misses the timeseries entirely,
+// and an empty range if it falls between two timesteps
+pub fn time_window(start_date: Option<BsonDateTime>, end_date: Option<BsonDateTime>, ts: &[BsonDateTime]) -> Result<Range<usize>, ApiError> {
+    let (first, last) = match (ts.first(), ts.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return Ok(0..0),
+    };
+
+    let outside_extent = start_date.is_some_and(|start_date| start_date > last)
+        || end_date.is_some_and(|end_date| end_date <= first);
+    if outside_extent {
+        return Err(ApiError::invalid("date_window", format!(
+            "'startDate' and 'endDate' should overlap this dataset's timeseries, which runs from {} to {}",
+            helpers::bsondate2string(&first),
+            helpers::bsondate2string(&last),
+        )));
+    }
 
-    let start_index = start_date.and_then(|start_date| {
-        ts.iter().position(|&t| t >= start_date)
+    let start_index = start_date.map(|start_date| {
+        ts.iter().position(|&t| t >= start_date).unwrap_or(ts.len())
     }).unwrap_or(0);
-    
-    let end_index = end_date.and_then(|end_date| {
-        ts.iter().rposition(|&t| t < end_date).map(|idx| idx + 1)
+
+    let end_index = end_date.map(|end_date| {
+        ts.iter().rposition(|&t| t < end_date).map(|idx| idx + 1).unwrap_or(0)
     }).unwrap_or(ts.len());
 
-    let time_window: Vec<String> = ts[start_index..end_index]
+    Ok(start_index..end_index.max(start_index))
+}
+
+// the timesteps a search keeps: those in its date window, then only the last 'mostrecent' of them
+pub fn selected_window(params: &SearchParams, ts: &[BsonDateTime]) -> Result<Range<usize>, ApiError> {
+    let window = time_window(params.start_date, params.end_date, ts)?;
+
+    match params.most_recent {
+        Some(most_recent) => Ok(window.end.saturating_sub(most_recent as usize).max(window.start)..window.end),
+        None => Ok(window),
+    }
+}
+
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn slice_timerange<T: schema::IsTimeseries>(window: &Range<usize>, ts: &[BsonDateTime], result: &mut T) -> Result<(), ApiError> {
+
+    let time_window: Vec<String> = ts[window.clone()]
         .iter()
-        .map(|t| helpers::bsondate2string(t))
+        .map(helpers::bsondate2string)
         .collect();
 
-    for result in &mut results {
-        let data = result.data();
-        *data = data.iter().map(|inner_vec| {
-            let slice = &inner_vec[start_index..end_index];
-            slice.to_vec()
-        }).collect();
+    let id = result._id();
+    let data = result.data();
+    *data = data.iter().map(|inner_vec| {
+        inner_vec.get(window.clone())
+            .map(|slice| slice.to_vec())
+            .ok_or_else(|| ApiError::Internal(format!("data for {} is shorter than the dataset timeseries", id)))
+    }).collect::<Result<_, _>>()?;
 
-        match result.timeseries() {
-            Some(timeseries) => *timeseries = time_window.clone(),
-            None => result.set_timeseries(time_window.clone()),
-        }
+    match result.timeseries() {
+        Some(timeseries) => *timeseries = time_window,
+        None => result.set_timeseries(time_window),
     }
 
-    results
+    Ok(())
+}
+
+// keep only the timesteps where every constrained variable is in range; false if none are left
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn filter_timeseries_values<T: schema::IsTimeseries>(constraints: &[ValueConstraint], ts: &[BsonDateTime], data_info: &schema::DataInfo, result: &mut T) -> bool {
+
+    let keep = satisfying_indexes(constraints, data_info, result.data());
 
+    let data = result.data();
+    *data = select_indexes(&keep, data);
+
+    match result.timeseries() {
+        Some(timeseries) => *timeseries = keep.iter().filter_map(|&i| timeseries.get(i).cloned()).collect(),
+        None => result.set_timeseries(keep.iter().filter_map(|&i| ts.get(i).map(helpers::bsondate2string)).collect()),
+    }
+
+    !keep.is_empty()
 }
 
-// todo: this will probably be generic over more than just Timeseries
-pub fn slice_data<T: schema::IsTimeseries>(data: Vec<String>, data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>), mut results: Vec<T>) -> Vec<T> {
+// false if none of the requested data is present
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn slice_data<T: schema::IsTimeseries>(data: &[String], data_info: &schema::DataInfo, result: &mut T) -> bool {
 
     if data.is_empty() {
-        for result in &mut results {
+        result.set_data(Vec::new());
+    } else if !data.contains(&"all".to_string()) {
+        let indexes = variable_indexes(data, data_info);
+
+        // only keep the requested data
+        let filtered_data = select_variables(&indexes, result.data());
+        if filtered_data.is_empty() {
+            return false;
+        }
+        result.set_data(filtered_data);
+
+        // create a custom data_info to go with this reduced data, and add it to the result object
+        result.set_data_info(subset_data_info(&indexes, data_info));
+
+        // if we set except_data_values, drop the data
+        if data.contains(&"except_data_values".to_string()) {
             result.set_data(Vec::new());
         }
+    }
+
+    true
+}
+
+// the data_info describing what slice_data leaves in each result's data
+pub fn selected_data_info(data: &[String], data_info: &schema::DataInfo) -> schema::DataInfo {
+    if data.is_empty() {
+        (Vec::new(), data_info.1.clone(), Vec::new())
     } else if data.contains(&"all".to_string()) {
-        return results;
+        data_info.clone()
+    } else if data.contains(&"except_data_values".to_string()) {
+        (Vec::new(), data_info.1.clone(), Vec::new())
     } else {
-        let indexes: Vec<usize> = data.iter()
-            .filter_map(|item| data_info.0.iter().position(|x| x == item))
-            .collect();
-
-        for result in &mut results {
-            // only keep the requested data
-            let filtered_data: Vec<Vec<f64>> = indexes.iter()
-                .filter_map(|&i| result.data().get(i).cloned())
-                .collect();
-            result.set_data(filtered_data);
-
-            // create a custom data_info to go with this reduced data, and add it to the result object
-            let filtered_data_info: (Vec<String>, Vec<String>, Vec<Vec<String>>) = (
-                indexes.iter().filter_map(|&i| data_info.0.get(i).cloned()).collect(),
-                data_info.1.clone(),
-                indexes.iter().filter_map(|&i| data_info.2.get(i).cloned()).collect(),
-            );
-            result.set_data_info(filtered_data_info);
+        subset_data_info(&variable_indexes(data, data_info), data_info)
+    }
+}
+
+pub fn timeseries_stub<T: schema::IsTimeseries>(results: &[T]) -> Vec<schema::TimeseriesStub> {
+    results.iter().map(timeseries_record_stub).collect()
+}
+
+pub fn timeseries_record_stub<T: schema::IsTimeseries>(result: &T) -> schema::TimeseriesStub {
+    schema::TimeseriesStub {
+        _id: result._id(),
+        longitude: result.longitude(),
+        latitude: result.latitude(),
+        level: result.level(),
+        metadata: result.metadata(),
+    }
+}
+
+pub fn transform_points<T: schema::IsPointData>(params: &SearchParams, results: Vec<T>) -> Vec<T> {
+    results.into_iter()
+        .filter_map(|result| transform_point_record(params, result))
+        .collect()
+}
+
+// as transform_timeseries_record, for a single point document
+#[tracing::instrument(name = "transform", level = "debug", skip_all)]
+pub fn transform_point_record<T: schema::IsPointData>(params: &SearchParams, mut result: T) -> Option<T> {
+
+    // apply appropriate transforms ////////////////////////////////////
+    // dates were already applied in the query; see filters::filter_points
+    if let Some(vertical_range) = &params.vertical_range {
+        if !slice_levels(vertical_range, &mut result) {
+            return None;
         }
+    }
+    if !params.data_constraints.is_empty() && !filter_point_values(&params.data_constraints, &mut result) {
+        return None;
+    }
+    if !slice_point_data(&params.data, &mut result) {
+        return None;
+    }
+
+    Some(result)
+}
+
+// keep only the levels whose pressure falls in [lower, upper); false if no levels are left
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn slice_levels<T: schema::IsPointData>(vertical_range: &[f64], result: &mut T) -> bool {
+
+    let data_info = result.data_info();
+    let data = result.data();
+
+    let levels: Vec<usize> = data_info.0.iter().position(|x| x == "pressure")
+        .and_then(|p| data.get(p))
+        .map(|pressure| pressure.iter().enumerate()
+            .filter(|(_, &p)| p >= vertical_range[0] && p < vertical_range[1])
+            .map(|(i, _)| i)
+            .collect())
+        .unwrap_or_default();
+
+    *data = select_indexes(&levels, data);
+
+    data.first().is_some_and(|variable| !variable.is_empty())
+}
+
+// keep only the levels where every constrained variable is in range; false if no levels are left
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn filter_point_values<T: schema::IsPointData>(constraints: &[ValueConstraint], result: &mut T) -> bool {
+
+    let data_info = result.data_info();
+    let keep = satisfying_indexes(constraints, &data_info, result.data());
+
+    let data = result.data();
+    *data = select_indexes(&keep, data);
+
+    data.first().is_some_and(|variable| !variable.is_empty())
+}
+
+// as slice_data, but each point document brings its own data_info
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn slice_point_data<T: schema::IsPointData>(data: &[String], result: &mut T) -> bool {
 
-        // if all the data is empty, remove the result
-        let mut i = 0;
-        while i != results.len() {
-            if results[i].data().is_empty() {
-                results.remove(i);
-            } else {
-                i += 1;
-            }
+    if data.is_empty() {
+        result.set_data(Vec::new());
+    } else if !data.contains(&"all".to_string()) {
+        let data_info = result.data_info();
+        let indexes = variable_indexes(data, &data_info);
+
+        let filtered_data = select_variables(&indexes, result.data());
+        if filtered_data.is_empty() {
+            return false;
         }
+        result.set_data(filtered_data);
+        result.set_data_info(subset_data_info(&indexes, &data_info));
 
-        // if we set except_data_values, drop the data from every result
+        // if we set except_data_values, drop the data
         if data.contains(&"except_data_values".to_string()) {
-            for result in &mut results {
-                result.set_data(Vec::new());
-            }
+            result.set_data(Vec::new());
         }
     }
 
-    results
+    true
 }
 
-pub fn timeseries_stub<T: schema::IsTimeseries>(results: Vec<T>) -> Vec<schema::TimeseriesStub> {
-    let r = results.iter().map(|result| {
-        schema::TimeseriesStub {
-            _id: result._id(),
-            longitude: result.longitude(),
-            latitude: result.latitude(),
-            level: result.level(),
-            metadata: result.metadata(),
-        }
-    }).collect();
+pub fn point_stub<T: schema::IsPointData>(results: &[T]) -> Vec<schema::PointStub> {
+    results.iter().map(point_record_stub).collect()
+}
+
+pub fn point_record_stub<T: schema::IsPointData>(result: &T) -> schema::PointStub {
+    schema::PointStub {
+        _id: result._id(),
+        longitude: result.longitude(),
+        latitude: result.latitude(),
+        timestamp: result.timestamp(),
+        metadata: result.metadata(),
+    }
+}
 
-    r
+// positions in data_info.0 of the requested variables, in the order requested; unknown names are skipped
+fn variable_indexes(data: &[String], data_info: &schema::DataInfo) -> Vec<usize> {
+    data.iter()
+        .filter_map(|item| data_info.0.iter().position(|x| x == item))
+        .collect()
 }
 
+fn select_variables(indexes: &[usize], data: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    indexes.iter()
+        .filter_map(|&i| data.get(i).cloned())
+        .collect()
+}
+
+// positions along the timeseries or level axis where every constraint holds;
+// a constraint on a variable this data doesn't have can never hold
+fn satisfying_indexes(constraints: &[ValueConstraint], data_info: &schema::DataInfo, data: &[Vec<f64>]) -> Vec<usize> {
+    let checks: Option<Vec<(&Vec<f64>, &ValueConstraint)>> = constraints.iter()
+        .map(|c| data_info.0.iter().position(|x| *x == c.variable).and_then(|i| data.get(i)).map(|values| (values, c)))
+        .collect();
+
+    match checks {
+        Some(checks) => (0..data.first().map_or(0, |values| values.len()))
+            .filter(|&j| checks.iter().all(|(values, c)| values.get(j).is_some_and(|&x| c.accepts(x))))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+// keep the given positions of every variable
+fn select_indexes(indexes: &[usize], data: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    data.iter().map(|variable| {
+        indexes.iter().filter_map(|&i| variable.get(i).copied()).collect()
+    }).collect()
+}
+
+fn subset_data_info(indexes: &[usize], data_info: &schema::DataInfo) -> schema::DataInfo {
+    (
+        indexes.iter().filter_map(|&i| data_info.0.get(i).cloned()).collect(),
+        data_info.1.clone(),
+        indexes.iter().filter_map(|&i| data_info.2.get(i).cloned()).collect(),
+    )
+}
@@ -0,0 +1,149 @@
+use crate::helpers::filters;
+use crate::helpers::transforms;
+use crate::helpers::schema;
+use crate::helpers::helpers;
+use crate::helpers::formats;
+use crate::helpers::params;
+use crate::helpers::pagination;
+use crate::helpers::errors::ApiError;
+use crate::helpers::state::AppState;
+use crate::routes::openapi::{ArgoResults, SearchErrors};
+use crate::helpers::store::{self, DocumentStream, QueryOptions};
+
+use actix_web::{get, web, HttpResponse};
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::collections::HashSet;
+
+#[utoipa::path(
+    get,
+    path = "/argo",
+    tag = "argo",
+    description = "Search Argo profiles",
+    params(params::SearchQuery),
+    responses(
+        (status = 200, description = "Matching profiles, in the requested format", content(
+            (ArgoResults = "application/json"),
+            (String = "application/x-ndjson"),
+            (Object = "application/geo+json"),
+        ),
+        headers(("x-next-page-token" = String, description = "Pass as pageToken for the next page; absent on the last page")),),
+        SearchErrors,
+    ),
+)]
+#[get("/argo")]
+pub async fn search_argo(state: web::Data<AppState>, query_params: web::Query<Vec<(String, String)>>) -> Result<HttpResponse, ApiError> {
+
+    // validate query params ////////////////////////////////////////
+    let params = params::SearchParams::from_pairs(query_params.into_inner()).map_err(ApiError::Validation)?;
+    if params.format.is_tabular() {
+        return Err(ApiError::invalid("format", "'format=csv', 'format=netcdf', 'format=arrow' and 'format=parquet' are only available for timeseries datasets"));
+    }
+    if params.most_recent.is_some() && (params.page > 0 || params.page_token.is_some() || params.page_size.is_some()) {
+        return Err(ApiError::invalid("mostrecent", "'mostrecent' returns a single page of point data, so can't be combined with 'page', 'pageSize' or 'pageToken'"));
+    }
+
+    // construct filter from query params //////////////////////////
+    let filter = filters::filter_points(&params)?;
+
+    // find this page of matching documents ///////////////////////
+    let page = match params.most_recent {
+        Some(most_recent) => pagination::most_recent(state.store.as_ref(), &state.config.argo_collection, filter, most_recent, &state.config).await?,
+        None => pagination::page(state.store.as_ref(), &state.config.argo_collection, filter, &params, &state.config).await?,
+    };
+
+    // Search for documents with matching filters //////////////////
+    let (records, next) = match page {
+        Some(page) => {
+            // mostrecent results come latest first
+            let options = QueryOptions {
+                sort: match params.most_recent {
+                    Some(_) => Some((String::from("timestamp"), -1)),
+                    None => Some((String::from("_id"), 1)),
+                },
+                ..QueryOptions::default()
+            };
+
+            let documents = state.store.find(&state.config.argo_collection, page.filter, options).await?;
+
+            // transform results as they arrive //////////////////////
+            let records = formats::counted(point_records(documents, params.clone()), state.metrics.documents.with_label_values(&["argo"]));
+            (records, page.next)
+        },
+        None => (stream::empty().boxed(), None),
+    };
+
+    let response = match params.envelope {
+        true => argo_envelope(&state, &params, records, next.clone()).await?,
+        false => argo_response(&state, &params, records).await?,
+    };
+    Ok(pagination::with_next_page(response, next))
+}
+
+// each profile carries its own data_info, so there's nothing shared to lift into the envelope
+async fn argo_envelope(state: &AppState, params: &params::SearchParams, records: formats::RecordStream<schema::ProfileSchema>, next: Option<String>) -> Result<HttpResponse, ApiError> {
+    let envelope = formats::Envelope {
+        next,
+        query: params.normalized(),
+        data_info: None,
+        timeseries: None,
+        shared: Vec::new(),
+    };
+
+    if params.compression == Some(params::Compression::Minimal) {
+        formats::json_envelope(records.map(|r| r.map(|r| transforms::point_record_stub(&r))).boxed(), envelope).await
+    } else if params.batchmeta {
+        let munged_results: Vec<schema::ProfileSchema> = records.try_collect().await?;
+        let unique_metadata: HashSet<_> = munged_results.iter()
+            .flat_map(|item| item.metadata.clone())
+            .collect();
+
+        let results = state.store.find_metadata(&state.config.argo_metadata_collection, unique_metadata.into_iter().collect()).await?;
+
+        formats::json_envelope(stream::iter(results.into_iter().map(Ok)).boxed(), envelope).await
+    } else {
+        formats::json_envelope(records, envelope).await
+    }
+}
+
+async fn argo_response(state: &AppState, params: &params::SearchParams, records: formats::RecordStream<schema::ProfileSchema>) -> Result<HttpResponse, ApiError> {
+
+    // return results ///////////////////////////////////////////////
+    // json and ndjson are written as the cursor is read; everything else needs the whole page
+    let minimal = params.compression == Some(params::Compression::Minimal);
+    match params.format {
+        params::Format::Json if minimal => return formats::json_array(records.map(|r| r.map(|r| transforms::point_record_stub(&r))).boxed()).await,
+        params::Format::Json if !params.batchmeta => return formats::json_array(records).await,
+        params::Format::Ndjson if minimal => return formats::ndjson(records.map(|r| r.map(|r| transforms::point_record_stub(&r))).boxed()).await,
+        params::Format::Ndjson => return formats::ndjson(records).await,
+        _ => (),
+    }
+
+    let munged_results: Vec<schema::ProfileSchema> = records.try_collect().await?;
+
+    if minimal {
+        formats::point_stub_collection(&transforms::point_stub(&munged_results))
+    } else if params.batchmeta {
+        let unique_metadata: HashSet<_> = munged_results.iter()
+            .flat_map(|item| item.metadata.clone())
+            .collect();
+
+        let results = state.store.find_metadata(&state.config.argo_metadata_collection, unique_metadata.into_iter().collect()).await?;
+
+        helpers::create_response(results)
+    } else if params.format == params::Format::Geojson {
+        formats::feature_collection(&munged_results)
+    } else {
+        helpers::create_response(munged_results)
+    }
+}
+
+// deserialize and transform each profile off the cursor, dropping the ones left with nothing to return
+fn point_records(documents: DocumentStream, params: params::SearchParams) -> formats::RecordStream<schema::ProfileSchema> {
+    documents.filter_map(move |document| {
+        let record = document
+            .and_then(store::from_document::<schema::ProfileSchema>)
+            .map(|result| transforms::transform_point_record(&params, result));
+        future::ready(record.transpose())
+    }).boxed()
+}
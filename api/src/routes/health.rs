@@ -0,0 +1,83 @@
+use crate::helpers::errors::ApiError;
+use crate::helpers::helpers;
+use crate::helpers::state::AppState;
+
+use actix_web::{get, web, HttpResponse};
+use serde_json::{json, Map, Value};
+use std::time::Duration;
+
+// a database that can't answer a ping this quickly counts as down
+const PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+// the process is up and serving requests
+#[utoipa::path(get, path = "/healthz", tag = "operations", responses((status = 200, description = "The process is up", body = Object)))]
+#[get("/healthz")]
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().json(json!({"status": "ok"}))
+}
+
+// the database answers and every configured dataset's metadata is loaded; 503 with what's missing otherwise
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Ready to serve every dataset", body = Object),
+        (status = 503, description = "Not ready; says whether the database or which datasets are missing", body = Object),
+    ),
+)]
+#[get("/readyz")]
+pub async fn readyz(state: web::Data<AppState>) -> HttpResponse {
+    let database = match actix_web::rt::time::timeout(PING_TIMEOUT, state.store.ping()).await {
+        Ok(Ok(())) => String::from("ok"),
+        Ok(Err(e)) => {
+            tracing::warn!("readiness ping failed: {}", e);
+            String::from("unreachable")
+        },
+        Err(_) => String::from("timed out"),
+    };
+
+    let datasets: Map<String, Value> = state.config.datasets.iter()
+        .map(|spec| (spec.name.clone(), json!(if state.is_loaded(&spec.name) { "loaded" } else { "loading" })))
+        .collect();
+
+    let ready = database == "ok" && datasets.values().all(|status| status == "loaded");
+    let body = json!({
+        "status": if ready { "ready" } else { "not ready" },
+        "database": database,
+        "datasets": datasets,
+    });
+
+    match ready {
+        true => HttpResponse::Ok().json(body),
+        false => HttpResponse::ServiceUnavailable().json(body),
+    }
+}
+
+// what's deployed: the build, and how fresh each dataset is.
+// set GIT_COMMIT when building to have it reported here
+#[utoipa::path(get, path = "/version", tag = "operations", responses((status = 200, description = "Build information and when each dataset was last updated", body = Object)))]
+#[get("/version")]
+pub async fn version(state: web::Data<AppState>) -> HttpResponse {
+    let datasets: Map<String, Value> = state.config.datasets.iter()
+        .map(|spec| {
+            let updated = state.dataset(&spec.name).ok().map(|dataset| helpers::bsondate2string(&dataset.date_updated));
+            (spec.name.clone(), json!({"date_updated_argovis": updated}))
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_commit": option_env!("GIT_COMMIT"),
+        "datasets": datasets,
+    }))
+}
+
+// prometheus metrics, for scraping
+#[utoipa::path(get, path = "/metrics", tag = "operations", responses((status = 200, description = "Prometheus metrics", body = String, content_type = "text/plain")))]
+#[get("/metrics")]
+pub async fn metrics(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (text, content_type) = state.metrics.render()?;
+    Ok(HttpResponse::Ok().content_type(content_type).body(text))
+}
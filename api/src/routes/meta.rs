@@ -0,0 +1,98 @@
+use crate::helpers::filters;
+use crate::helpers::helpers;
+use crate::helpers::params;
+use crate::helpers::errors::{ApiError, ErrorBody};
+use crate::helpers::datasets::{Dataset, DatasetSummary};
+use crate::helpers::state::AppState;
+use crate::routes::openapi::{SearchErrors, TimeseriesMetadata};
+use crate::helpers::store::QueryOptions;
+
+use actix_web::{get, web, HttpResponse};
+use futures::stream::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use std::collections::BTreeSet;
+
+#[utoipa::path(
+    get,
+    path = "/timeseries/{dataset}/meta",
+    tag = "timeseries",
+    description = "Metadata documents of a gridded timeseries product, by _id or by the region their grid cells fall in",
+    params(("dataset" = String, Path, description = "bsose, noaasst, copernicussla or ccmpwind, as configured"), params::MetaQuery),
+    responses(
+        (status = 200, description = "Matching metadata documents", body = TimeseriesMetadata),
+        SearchErrors,
+    ),
+)]
+#[get("/timeseries/{dataset}/meta")]
+pub async fn timeseries_meta(state: web::Data<AppState>, path: web::Path<String>, query_params: web::Query<Vec<(String, String)>>) -> Result<HttpResponse, ApiError> {
+    let params = params::SearchParams::from_meta_pairs(query_params.into_inner()).map_err(ApiError::Validation)?;
+    let dataset = state.dataset(&path.into_inner())?;
+
+    // find the metadata ids asked for, directly or by region //////
+    let ids: Vec<String> = match &params.id {
+        Some(ids) => ids.split(',').map(String::from).collect(),
+        None => region_metadata_ids(&state, &dataset, &params).await?.into_iter().collect(),
+    };
+    if ids.len() as i64 > state.config.max_page_size {
+        return Err(ApiError::invalid("id", format!("'id' should list at most {} metadata ids", state.config.max_page_size)));
+    }
+
+    // only this dataset's metadata is served here /////////////////
+    let filter = doc! { "_id": { "$in": ids }, "data_type": &dataset.spec.data_type };
+    let options = QueryOptions {
+        sort: Some((String::from("_id"), 1)),
+        ..QueryOptions::default()
+    };
+    let documents: Vec<Document> = state.store.find(&dataset.spec.metadata_collection, filter, options).await?.try_collect().await?;
+
+    helpers::create_response(documents)
+}
+
+// metadata documents carry no location, so regions are matched against the data documents that reference them;
+// no more of those are read than a search could return in one page
+async fn region_metadata_ids(state: &AppState, dataset: &Dataset, params: &params::SearchParams) -> Result<BTreeSet<String>, ApiError> {
+    let max_documents = state.config.max_page_size;
+    let filter = filters::filter_timeseries(params)?;
+    let options = QueryOptions {
+        projection: Some(doc! { "metadata": 1 }),
+        limit: Some(max_documents + 1),
+        ..QueryOptions::default()
+    };
+
+    let (documents, ids) = state.store.find(&dataset.spec.collection, filter, options).await?
+        .try_fold((0, BTreeSet::new()), |(documents, mut ids), document| async move {
+            if let Ok(metadata) = document.get_array("metadata") {
+                ids.extend(metadata.iter().filter_map(|id| id.as_str()).map(String::from));
+            }
+            Ok((documents + 1, ids))
+        })
+        .await?;
+
+    if documents > max_documents {
+        return Err(ApiError::PayloadTooLarge(format!(
+            "This region covers more than {} grid cells; narrow it with a smaller polygon, box or radius, or ask for metadata by id",
+            max_documents,
+        )));
+    }
+
+    Ok(ids)
+}
+
+// the dataset as loaded at startup; no data documents are read
+#[utoipa::path(
+    get,
+    path = "/timeseries/{dataset}/summary",
+    tag = "timeseries",
+    description = "Timeseries extent, data_info, sources and freshness of a gridded timeseries product",
+    params(("dataset" = String, Path, description = "bsose, noaasst, copernicussla or ccmpwind, as configured")),
+    responses(
+        (status = 200, description = "The dataset's summary", body = DatasetSummary),
+        (status = 404, description = "No such dataset", body = ErrorBody),
+        (status = 503, description = "The dataset is still loading", body = ErrorBody),
+    ),
+)]
+#[get("/timeseries/{dataset}/summary")]
+pub async fn timeseries_summary(state: web::Data<AppState>, path: web::Path<String>) -> Result<HttpResponse, ApiError> {
+    let dataset = state.dataset(&path.into_inner())?;
+    Ok(HttpResponse::Ok().json(dataset.summary()))
+}
@@ -0,0 +1,35 @@
+pub mod search;
+pub use search::*;
+
+pub mod argo;
+pub use argo::*;
+
+pub mod meta;
+pub use meta::*;
+
+pub mod vocabulary;
+pub use vocabulary::*;
+
+pub mod health;
+pub use health::*;
+
+pub mod openapi;
+
+use crate::helpers::errors::ApiError;
+use actix_web::web;
+
+// every route the API serves, for the server and for test instances alike
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::QueryConfig::default().error_handler(|err, _req| ApiError::invalid("malformed_query", err.to_string()).into()))
+        .service(search_data_schema)
+        .service(search_timeseries)
+        .service(timeseries_meta)
+        .service(timeseries_summary)
+        .service(timeseries_vocabulary)
+        .service(search_argo)
+        .service(healthz)
+        .service(readyz)
+        .service(version)
+        .service(metrics)
+        .service(openapi::swagger_ui());
+}
@@ -0,0 +1,123 @@
+use crate::helpers::datasets::{DatasetSummary, TimeseriesExtent};
+use crate::helpers::errors::ErrorBody;
+use crate::helpers::params::{Compression, Format};
+use crate::helpers::schema::{BsoseMeta, BsoseSchema, GridMeta, GridSchema, PointStub, ProfileSchema, SourceMeta, TimeseriesStub};
+
+use serde::Serialize;
+use utoipa::openapi::schema::{KnownFormat, ObjectBuilder, SchemaFormat, Type};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{IntoResponses, OpenApi, PartialSchema, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+// where the document is served, and the swagger ui that browses it
+pub const OPENAPI_PATH: &str = "/openapi.json";
+pub const DOCS_PATH: &str = "/docs/";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Argovis API", description = "Search Argo profiles and gridded ocean timeseries products by region, depth, time and variable."),
+    paths(
+        super::search::search_data_schema,
+        super::search::search_timeseries,
+        super::meta::timeseries_meta,
+        super::meta::timeseries_summary,
+        super::vocabulary::timeseries_vocabulary,
+        super::argo::search_argo,
+        super::health::healthz,
+        super::health::readyz,
+        super::health::version,
+        super::health::metrics,
+    ),
+    components(schemas(
+        BsoseSchema, BsoseMeta, GridSchema, GridMeta, ProfileSchema, TimeseriesStub, PointStub,
+        DatasetSummary, TimeseriesExtent, SourceMeta, Compression, Format, ErrorBody,
+    )),
+)]
+pub struct ApiDoc;
+
+pub fn swagger_ui() -> SwaggerUi {
+    SwaggerUi::new(format!("{}{{_:.*}}", DOCS_PATH)).url(OPENAPI_PATH, ApiDoc::openapi())
+}
+
+// response bodies, for documentation only ////////////////////////////////////
+
+// json results from /search: documents, stubs with compression=minimal, or metadata with batchmeta
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum BsoseResults {
+    Documents(Vec<BsoseSchema>),
+    Stubs(Vec<TimeseriesStub>),
+    Metadata(Vec<BsoseMeta>),
+}
+
+// json results from /timeseries/{dataset}; bsose documents, or those of the other gridded products
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum TimeseriesResults {
+    Bsose(Vec<BsoseSchema>),
+    Grid(Vec<GridSchema>),
+    Stubs(Vec<TimeseriesStub>),
+    BsoseMetadata(Vec<BsoseMeta>),
+    Metadata(Vec<GridMeta>),
+}
+
+// json results from /timeseries/{dataset}/meta
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum TimeseriesMetadata {
+    Bsose(Vec<BsoseMeta>),
+    Grid(Vec<GridMeta>),
+}
+
+// json results from /argo; metadata documents with batchmeta have no fixed schema
+#[allow(dead_code)]
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ArgoResults {
+    Profiles(Vec<ProfileSchema>),
+    Stubs(Vec<PointStub>),
+    Metadata(Vec<MetadataDocument>),
+}
+
+#[derive(Serialize)]
+pub struct MetadataDocument;
+
+impl PartialSchema for MetadataDocument {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new().schema_type(Type::Object).into()
+    }
+}
+
+impl ToSchema for MetadataDocument {}
+
+// netcdf, arrow and parquet bodies
+pub struct Binary;
+
+impl PartialSchema for Binary {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new().schema_type(Type::String).format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary))).into()
+    }
+}
+
+impl ToSchema for Binary {}
+
+// what any search can fail with
+#[allow(dead_code)]
+#[derive(IntoResponses)]
+pub enum SearchErrors {
+    #[response(status = 400, description = "Invalid query parameters; details lists every problem found")]
+    BadRequest(ErrorBody),
+    #[response(status = 401, description = "The x-argokey header isn't a valid API key")]
+    Unauthorized(ErrorBody),
+    #[response(status = 404, description = "No results, or no such dataset")]
+    NotFound(ErrorBody),
+    #[response(status = 413, description = "The search would return too much per page; narrow it")]
+    PayloadTooLarge(ErrorBody),
+    #[response(status = 429, description = "Rate limited; Retry-After says when to try again")]
+    TooManyRequests(ErrorBody),
+    #[response(status = 503, description = "The database can't be reached, or the dataset is still loading")]
+    Unavailable(ErrorBody),
+}
@@ -0,0 +1,239 @@
+use crate::helpers::filters;
+use crate::helpers::transforms;
+use crate::helpers::schema;
+use crate::helpers::helpers;
+use crate::helpers::formats;
+use crate::helpers::netcdf;
+use crate::helpers::columnar;
+use crate::helpers::cost;
+use crate::helpers::params;
+use crate::helpers::pagination;
+use crate::helpers::errors::ApiError;
+use crate::helpers::datasets::{Dataset, SchemaKind};
+use crate::routes::openapi::{Binary, BsoseResults, SearchErrors, TimeseriesResults};
+use crate::helpers::state::AppState;
+use crate::helpers::store::{self, DocumentStream, QueryOptions};
+
+use actix_web::{get, web, HttpResponse};
+use futures::future;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+// legacy route, kept for existing clients; equivalent to /timeseries/bsose
+#[utoipa::path(
+    get,
+    path = "/search",
+    tag = "timeseries",
+    description = "Search BSOSE; the same as /timeseries/bsose",
+    params(params::SearchQuery),
+    responses(
+        (status = 200, description = "Matching documents, in the requested format", content(
+            (BsoseResults = "application/json"),
+            (String = "application/x-ndjson"),
+            (String = "text/csv"),
+            (Object = "application/geo+json"),
+            (Binary = "application/x-netcdf"),
+            (Binary = "application/vnd.apache.arrow.stream"),
+            (Binary = "application/vnd.apache.parquet"),
+        ),
+        headers(("x-next-page-token" = String, description = "Pass as pageToken for the next page; absent on the last page")),),
+        SearchErrors,
+    ),
+)]
+#[get("/search")]
+pub async fn search_data_schema(state: web::Data<AppState>, query_params: web::Query<Vec<(String, String)>>) -> Result<HttpResponse, ApiError> {
+    let params = params::SearchParams::from_pairs(query_params.into_inner()).map_err(ApiError::Validation)?;
+    let dataset = state.dataset("bsose")?;
+
+    search_dataset(&state, &dataset, &params).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/timeseries/{dataset}",
+    tag = "timeseries",
+    description = "Search a gridded timeseries product",
+    params(("dataset" = String, Path, description = "bsose, noaasst, copernicussla or ccmpwind, as configured"), params::SearchQuery),
+    responses(
+        (status = 200, description = "Matching documents, in the requested format", content(
+            (TimeseriesResults = "application/json"),
+            (String = "application/x-ndjson"),
+            (String = "text/csv"),
+            (Object = "application/geo+json"),
+            (Binary = "application/x-netcdf"),
+            (Binary = "application/vnd.apache.arrow.stream"),
+            (Binary = "application/vnd.apache.parquet"),
+        ),
+        headers(("x-next-page-token" = String, description = "Pass as pageToken for the next page; absent on the last page")),),
+        SearchErrors,
+    ),
+)]
+#[get("/timeseries/{dataset}")]
+pub async fn search_timeseries(state: web::Data<AppState>, path: web::Path<String>, query_params: web::Query<Vec<(String, String)>>) -> Result<HttpResponse, ApiError> {
+    let params = params::SearchParams::from_pairs(query_params.into_inner()).map_err(ApiError::Validation)?;
+    let dataset = state.dataset(&path.into_inner())?;
+
+    search_dataset(&state, &dataset, &params).await
+}
+
+async fn search_dataset(state: &AppState, dataset: &Arc<Dataset>, params: &params::SearchParams) -> Result<HttpResponse, ApiError> {
+    match dataset.spec.schema {
+        SchemaKind::Bsose => search::<schema::BsoseSchema>(state, dataset, params).await,
+        SchemaKind::Grid => search::<schema::GridSchema>(state, dataset, params).await,
+    }
+}
+
+async fn search<T>(state: &AppState, dataset: &Arc<Dataset>, params: &params::SearchParams) -> Result<HttpResponse, ApiError>
+where
+    T: schema::IsTimeseries + DeserializeOwned + Serialize + Send + 'static,
+{
+    // refuse variables the dataset doesn't have //////////////////
+    params.check_variables(&dataset.data_info.0).map_err(ApiError::Validation)?;
+    if params.format == params::Format::Netcdf {
+        netcdf::check_variable_names(&transforms::selected_data_info(&params.data, &dataset.data_info))?;
+    }
+
+    // check the date window against the dataset's timeseries //////
+    let window = transforms::selected_window(params, &dataset.timeseries)?;
+
+    // refuse searches too big to serve ////////////////////////////
+    let page_size = pagination::page_size(params, &state.config)?;
+    cost::check_timeseries_cost(params, dataset, page_size, state.config.max_response_bytes)?;
+
+    // construct filter from query params //////////////////////////
+    let filter = filters::filter_timeseries(params)?;
+
+    // find this page of matching documents ///////////////////////
+    let page = match window.is_empty() {
+        true => None,
+        false => pagination::page(state.store.as_ref(), &dataset.spec.collection, filter, params, &state.config).await?,
+    };
+
+    // Search for documents with matching filters //////////////////
+    let (records, next) = match page {
+        Some(page) => {
+            let options = QueryOptions {
+                sort: Some((String::from("_id"), 1)),
+                ..QueryOptions::default()
+            };
+
+            let documents = state.store.find(&dataset.spec.collection, page.filter, options).await?;
+
+            // transform results as they arrive //////////////////////
+            let records = timeseries_records::<T>(documents, params.clone(), dataset.clone());
+            let records = formats::counted(records, state.metrics.documents.with_label_values(&[dataset.spec.name.as_str()]));
+            (records, page.next)
+        },
+        None => (stream::empty().boxed(), None),
+    };
+
+    let response = match params.envelope {
+        true => {
+            let timeseries = dataset.timeseries[window].iter().map(helpers::bsondate2string).collect();
+            timeseries_envelope(state, dataset, params, records, next.clone(), timeseries).await?
+        },
+        false => timeseries_response(state, dataset, params, records).await?,
+    };
+    Ok(pagination::with_next_page(response, next))
+}
+
+// data_info and the timeseries are given once for the whole page, unless value constraints left records with their own timesteps
+async fn timeseries_envelope<T>(state: &AppState, dataset: &Dataset, params: &params::SearchParams, records: formats::RecordStream<T>, next: Option<String>, timeseries: Vec<String>) -> Result<HttpResponse, ApiError>
+where
+    T: schema::IsTimeseries + Serialize + Send + 'static,
+{
+    let envelope = formats::Envelope {
+        next,
+        query: params.normalized(),
+        data_info: Some(transforms::selected_data_info(&params.data, &dataset.data_info)),
+        timeseries: Some(timeseries),
+        shared: match params.data_constraints.is_empty() {
+            true => vec!["data_info", "timeseries"],
+            false => vec!["data_info"],
+        },
+    };
+
+    if params.compression == Some(params::Compression::Minimal) {
+        formats::json_envelope(records.map(|r| r.map(|r| transforms::timeseries_record_stub(&r))).boxed(), envelope).await
+    } else if params.batchmeta {
+        let munged_results: Vec<T> = records.try_collect().await?;
+        let unique_metadata: HashSet<_> = munged_results.iter()
+            .flat_map(|item| item.metadata())
+            .collect();
+
+        let results = state.store.find_metadata(&dataset.spec.metadata_collection, unique_metadata.into_iter().collect()).await?;
+
+        // metadata documents carry neither the data's data_info nor its timeseries
+        let envelope = formats::Envelope { data_info: None, timeseries: None, shared: Vec::new(), ..envelope };
+        formats::json_envelope(stream::iter(results.into_iter().map(Ok)).boxed(), envelope).await
+    } else {
+        formats::json_envelope(records, envelope).await
+    }
+}
+
+async fn timeseries_response<T>(state: &AppState, dataset: &Dataset, params: &params::SearchParams, records: formats::RecordStream<T>) -> Result<HttpResponse, ApiError>
+where
+    T: schema::IsTimeseries + Serialize + Send + 'static,
+{
+    // return results ///////////////////////////////////////////////
+    // json, ndjson and csv are written as the cursor is read; everything else needs the whole page
+    let minimal = params.compression == Some(params::Compression::Minimal);
+    match params.format {
+        params::Format::Json if minimal => return formats::json_array(records.map(|r| r.map(|r| transforms::timeseries_record_stub(&r))).boxed()).await,
+        params::Format::Json if !params.batchmeta => return formats::json_array(records).await,
+        params::Format::Ndjson if minimal => return formats::ndjson(records.map(|r| r.map(|r| transforms::timeseries_record_stub(&r))).boxed()).await,
+        params::Format::Ndjson => return formats::ndjson(records).await,
+        params::Format::Csv => {
+            let data_info = transforms::selected_data_info(&params.data, &dataset.data_info);
+            let timeseries = dataset.timeseries.iter().map(helpers::bsondate2string).collect();
+            return formats::timeseries_csv(records, data_info, timeseries).await;
+        },
+        _ => (),
+    }
+
+    // file formats need the whole page; like csv, no results is a 404 rather than an empty file
+    if matches!(params.format, params::Format::Netcdf | params::Format::Arrow | params::Format::Parquet) {
+        let munged_results: Vec<T> = formats::first_or_not_found(records).await?.try_collect().await?;
+        let data_info = transforms::selected_data_info(&params.data, &dataset.data_info);
+        let timeseries: Vec<String> = dataset.timeseries.iter().map(helpers::bsondate2string).collect();
+        return match params.format {
+            params::Format::Netcdf => netcdf::timeseries_netcdf(&dataset.spec.name, munged_results, &data_info, &timeseries),
+            params::Format::Arrow => columnar::timeseries_arrow(munged_results, &data_info, &timeseries),
+            _ => columnar::timeseries_parquet(&dataset.spec.name, munged_results, &data_info, &timeseries),
+        };
+    }
+
+    let munged_results: Vec<T> = records.try_collect().await?;
+
+    if minimal {
+        formats::timeseries_stub_collection(&transforms::timeseries_stub(&munged_results))
+    } else if params.batchmeta {
+        let unique_metadata: HashSet<_> = munged_results.iter()
+            .flat_map(|item| item.metadata())
+            .collect();
+
+        let results = state.store.find_metadata(&dataset.spec.metadata_collection, unique_metadata.into_iter().collect()).await?;
+
+        helpers::create_response(results)
+    } else if params.format == params::Format::Geojson {
+        formats::feature_collection(&munged_results)
+    } else {
+        helpers::create_response(munged_results)
+    }
+}
+
+// deserialize and transform each document off the cursor, dropping the ones left with nothing to return;
+// the stream outlives the handler, so it shares the dataset rather than copying its timeseries and data_info
+fn timeseries_records<T>(documents: DocumentStream, params: params::SearchParams, dataset: Arc<Dataset>) -> formats::RecordStream<T>
+where
+    T: schema::IsTimeseries + DeserializeOwned + Send + 'static,
+{
+    documents.filter_map(move |document| {
+        let record = document
+            .and_then(store::from_document::<T>)
+            .and_then(|result| transforms::transform_timeseries_record(&params, &dataset.timeseries, &dataset.data_info, result));
+        future::ready(record.transpose())
+    }).boxed()
+}
@@ -0,0 +1,38 @@
+use crate::helpers::params;
+use crate::helpers::errors::ApiError;
+use crate::helpers::state::AppState;
+use crate::routes::openapi::SearchErrors;
+
+use actix_web::{get, web, HttpResponse};
+use serde_json::{json, Value};
+
+#[utoipa::path(
+    get,
+    path = "/timeseries/{dataset}/vocabulary",
+    tag = "timeseries",
+    description = "Valid values of a search parameter for a gridded timeseries product; data variables come from its data_info, basins and levels from its documents as of loading, and unavailable until they've loaded",
+    params(("dataset" = String, Path, description = "bsose, noaasst, copernicussla or ccmpwind, as configured"), params::VocabularyQuery),
+    responses(
+        (status = 200, description = "{parameter, values}", body = Object),
+        SearchErrors,
+    ),
+)]
+#[get("/timeseries/{dataset}/vocabulary")]
+pub async fn timeseries_vocabulary(state: web::Data<AppState>, path: web::Path<String>, query_params: web::Query<Vec<(String, String)>>) -> Result<HttpResponse, ApiError> {
+    let parameter = params::vocabulary_parameter(query_params.into_inner()).map_err(ApiError::Validation)?;
+    let dataset = state.dataset(&path.into_inner())?;
+
+    let values: Vec<Value> = match parameter.as_str() {
+        "data" => dataset.data_info.0.iter().map(String::as_str)
+            .chain(["all", "except_data_values"])
+            .map(Value::from)
+            .collect(),
+        "compression" => vec![Value::from("minimal")],
+        "format" => params::FORMATS.iter().map(|format| Value::from(*format)).collect(),
+        "basin" => state.vocabulary(&dataset.spec.name)?.basins.clone(),
+        "level" => state.vocabulary(&dataset.spec.name)?.levels.clone(),
+        _ => return Err(ApiError::invalid("parameter", format!("'parameter' should be one of {}", params::VOCABULARY_PARAMETERS.join(", ")))),
+    };
+
+    Ok(HttpResponse::Ok().json(json!({"parameter": parameter, "values": values})))
+}
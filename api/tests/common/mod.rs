@@ -0,0 +1,202 @@
+// shared fixtures for the HTTP tests: an AppState over a MemoryStore, served through routes::configure
+// and the same middleware as main.rs
+#![allow(dead_code)]
+
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use actix_web::web::{self, Bytes};
+use actix_web::{middleware, test, App};
+use api::helpers::*;
+use api::routes;
+use mongodb::bson::{doc, Bson, DateTime, Document};
+use serde_json::Value;
+use std::sync::Arc;
+
+// 2020-09-13T12:26:40Z, and one day apart after that
+pub const T0: i64 = 1_600_000_000_000;
+pub const DAY: i64 = 86_400_000;
+
+pub fn timeseries() -> Vec<DateTime> {
+    (0..4).map(|i| DateTime::from_millis(T0 + i * DAY)).collect()
+}
+
+pub fn bsose_metadata() -> Document {
+    doc! {
+        "_id": "m1",
+        "data_type": "BSOSE-profile",
+        "data_info": [["temperature", "salinity"], ["units"], [["degC"], ["psu"]]],
+        "date_updated_argovis": DateTime::from_millis(T0),
+        "timeseries": timeseries(),
+        "source": [{"source": ["bsose"], "file": "bsose_i122_2013to2017_1day_Theta.nc"}],
+        "cell_area": 1.0,
+        "ocean_depth": 4000.0,
+        "depth_r0_to_bottom": 4000.0,
+        "interior_2d_mask": true,
+        "depth_r0_to_ref_surface": 0.0,
+    }
+}
+
+// temperature 1..4 and salinity 30, 31, 36, 37 over the four timesteps
+pub fn bsose_document(id: &str, lon: f64, lat: f64, level: f64) -> Document {
+    doc! {
+        "_id": id,
+        "metadata": ["m1"],
+        "basin": 1.0,
+        "geolocation": {"type": "Point", "coordinates": [lon, lat]},
+        "level": level,
+        "cell_vertical_fraction": 1.0,
+        "sea_binary_mask_at_t_locaiton": true,
+        "ctrl_vector_3d_mask": true,
+        "cell_z_size": 1.0,
+        "reference_density_profile": 1.0,
+        "data": [[1.0, 2.0, 3.0, 4.0], [30.0, 31.0, 36.0, 37.0]],
+    }
+}
+
+pub fn sst_metadata() -> Document {
+    doc! {
+        "_id": "noaa-oi-sst-v2",
+        "data_type": "noaa-oi-sst-v2",
+        "data_info": [["sst"], ["units"], [["degC"]]],
+        "date_updated_argovis": DateTime::from_millis(T0),
+        "timeseries": timeseries(),
+        "source": [{"source": ["NOAA Optimum Interpolation SST V2"], "file": "sst.wkmean.1990-present.nc"}],
+    }
+}
+
+// surface products have no level; cells under sea ice are null
+pub fn sst_document(id: &str, lon: f64, lat: f64) -> Document {
+    doc! {
+        "_id": id,
+        "metadata": ["noaa-oi-sst-v2"],
+        "basin": 1.0,
+        "geolocation": {"type": "Point", "coordinates": [lon, lat]},
+        "data": [[20.5, Bson::Null, 21.0, 21.5]],
+    }
+}
+
+pub fn argo_document(i: i64) -> Document {
+    doc! {
+        "_id": format!("4902911_00{}", i),
+        "metadata": ["4902911_m0"],
+        "basin": 1.0,
+        "geolocation": {"type": "Point", "coordinates": [10.0 + i as f64, 10.0]},
+        "geolocation_argoqc": 1,
+        "timestamp": DateTime::from_millis(T0 + i * DAY),
+        "timestamp_argoqc": 1,
+        "cycle_number": i,
+        "data": [[5.0, 50.0, 500.0], [20.0, Bson::Null, 4.0 + i as f64]],
+        "data_info": [["pressure", "temperature"], ["units"], [["dbar"], ["degC"]]],
+    }
+}
+
+// three bsose documents, two near (10, 10) at 5m and one near the dateline at 50m, two sst documents,
+// and three argo profiles a day apart
+pub fn store() -> MemoryStore {
+    MemoryStore::new()
+        .with_documents("timeseriesMeta", vec![bsose_metadata(), sst_metadata()])
+        .with_documents("bsose", vec![
+            bsose_document("a", 10.0, 10.0, 5.0),
+            bsose_document("b", -170.0, -10.0, 50.0),
+            bsose_document("c", 11.0, 10.0, 5.0),
+        ])
+        .with_documents("noaaOIsst", vec![sst_document("s1", 10.5, 10.5), sst_document("s2", 100.5, 10.5)])
+        .with_documents("argo", (0..3).map(argo_document).collect())
+        .with_documents("argoMeta", vec![doc! {"_id": "4902911_m0", "platform": "4902911"}])
+        .with_documents("apiKeys", vec![doc! {"key": "k1", "tier": "standard"}])
+}
+
+// bsose and noaasst are configured, with limits too generous to get in the way
+pub fn config() -> Config {
+    let mut config = Config::default();
+    config.datasets.truncate(2);
+    for limit in config.rate_limits.values_mut() {
+        limit.burst = 1e6;
+    }
+    config
+}
+
+pub struct TestApi {
+    pub state: web::Data<AppState>,
+}
+
+pub struct Response {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Response {
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_else(|e| panic!("not json ({}): {}", e, self.text()))
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers.get(name).map(|value| value.to_str().unwrap().to_string())
+    }
+
+    // the _ids of a json array of documents
+    pub fn ids(&self) -> Vec<String> {
+        self.json().as_array().expect("a json array").iter()
+            .map(|document| document["_id"].as_str().expect("an _id").to_string())
+            .collect()
+    }
+
+    // the details of a validation error
+    pub fn problems(&self) -> Vec<String> {
+        self.json()["details"].as_array().expect("validation details").iter()
+            .map(|problem| problem.as_str().unwrap().to_string())
+            .collect()
+    }
+}
+
+impl TestApi {
+    // the default fixtures, with bsose loaded
+    pub async fn new() -> TestApi {
+        TestApi::with(store(), config()).await
+    }
+
+    // every dataset in config.datasets is registered, and its vocabulary loaded, before the first request
+    pub async fn with(store: MemoryStore, config: Config) -> TestApi {
+        TestApi::serve(AppState::new(Arc::new(store), config)).await
+    }
+
+    // serve a state built by the test, like one with its own key store
+    pub async fn serve(state: AppState) -> TestApi {
+        for spec in state.config.datasets.clone() {
+            state.register(spec.clone()).await.expect("fixtures should register");
+            // as when serving, a vocabulary that can't be read leaves only /vocabulary unavailable
+            let _ = state.load_vocabulary(&spec).await;
+        }
+        TestApi { state: web::Data::new(state) }
+    }
+
+    pub async fn get(&self, uri: &str) -> Response {
+        self.call(test::TestRequest::get().uri(uri)).await
+    }
+
+    pub async fn get_with_key(&self, uri: &str, key: &str) -> Response {
+        self.call(test::TestRequest::get().uri(uri).insert_header(("x-argokey", key))).await
+    }
+
+    pub async fn call(&self, request: test::TestRequest) -> Response {
+        let app = test::init_service(
+            App::new()
+                .app_data(self.state.clone())
+                .wrap(middleware::from_fn(rate_limit))
+                .wrap(middleware::from_fn(trace_request))
+                .wrap(middleware::from_fn(track))
+                .configure(routes::configure),
+        ).await;
+
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = test::read_body(response).await;
+        Response { status, headers, body }
+    }
+}
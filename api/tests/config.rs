@@ -0,0 +1,165 @@
+use api::helpers::config::{Config, LogFormat};
+
+const URI: &str = "mongodb_uri = \"mongodb://localhost:27017\"\n";
+
+fn env(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+    pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<Vec<_>>().into_iter()
+}
+
+fn load(toml_text: &str, pairs: &[(&str, &str)]) -> Result<Config, Vec<String>> {
+    Config::from_sources(toml_text, env(pairs))
+}
+
+// the problems validate() finds with `toml_text` added to a config that's otherwise fine
+fn problems(toml_text: &str) -> Vec<String> {
+    load(&format!("{}{}", URI, toml_text), &[]).unwrap_err()
+}
+
+// sources /////////////////////////////////////////////////////////////////////
+
+#[test]
+fn file_overrides_defaults() {
+    let config = load(&format!("{}page_size = 50\nlog_format = \"text\"\n", URI), &[]).unwrap();
+
+    assert_eq!(config.page_size, 50);
+    assert_eq!(config.log_format, LogFormat::Text);
+    assert_eq!(config.max_page_size, 10000);
+}
+
+#[test]
+fn environment_overrides_the_file() {
+    let config = load(&format!("{}page_size = 50\ndatabase = \"argo\"\n", URI), &[
+        ("ARGOVIS_PAGE_SIZE", "500"),
+        ("ARGOVIS_DATABASE", "staging"),
+        ("ARGOVIS_TRUST_PROXY_HEADERS", "true"),
+        ("ARGOVIS_WORKERS", "4"),
+    ]).unwrap();
+
+    assert_eq!(config.page_size, 500);
+    assert_eq!(config.database, "staging");
+    assert!(config.trust_proxy_headers);
+    assert_eq!(config.workers, Some(4));
+}
+
+#[test]
+fn mongodb_uri_is_read_from_its_usual_variable() {
+    let config = load("", &[("MONGODB_URI", "mongodb://db:27017")]).unwrap();
+    assert_eq!(config.mongodb_uri.as_deref(), Some("mongodb://db:27017"));
+
+    let prefixed = load("", &[("ARGOVIS_MONGODB_URI", "mongodb://other:27017")]).unwrap();
+    assert_eq!(prefixed.mongodb_uri.as_deref(), Some("mongodb://other:27017"));
+}
+
+#[test]
+fn string_fields_keep_values_that_look_like_numbers_or_booleans() {
+    let config = load(URI, &[
+        ("ARGOVIS_DATABASE", "2024"),
+        ("ARGOVIS_SERVICE_NAME", "true"),
+        ("ARGOVIS_API_KEYS_FILE", "1.5"),
+    ]).unwrap();
+
+    assert_eq!(config.database, "2024");
+    assert_eq!(config.service_name, "true");
+    assert_eq!(config.api_keys_file.as_deref(), Some("1.5"));
+}
+
+#[test]
+fn unknown_environment_variables_are_ignored() {
+    let config = load(URI, &[
+        ("ARGOVIS_API_SERVICE_HOST", "10.0.0.1"),
+        ("ARGOVIS_CONFIG", "config.toml"),
+        ("PATH", "/usr/bin"),
+    ]).unwrap();
+
+    assert_eq!(config.bind_address, "0.0.0.0:8080");
+}
+
+#[test]
+fn unknown_file_keys_are_refused() {
+    let problems = load(&format!("{}page_sise = 50\n", URI), &[]).unwrap_err();
+
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].contains("unknown field `page_sise`"), "{}", problems[0]);
+}
+
+#[test]
+fn environment_values_of_the_wrong_type_are_refused() {
+    let problems = load(URI, &[("ARGOVIS_PAGE_SIZE", "lots")]).unwrap_err();
+    assert!(problems[0].starts_with("config isn't valid"), "{}", problems[0]);
+
+    let table = load(URI, &[("ARGOVIS_PAGE_SIZE", "{ a = 1 }")]).unwrap_err();
+    assert_eq!(table, ["ARGOVIS_PAGE_SIZE should be a single value, like 500, true or 0.0.0.0:8080"]);
+}
+
+#[test]
+fn invalid_file_is_reported() {
+    let problems = load("page_size = ", &[]).unwrap_err();
+    assert!(problems[0].starts_with("config file isn't valid"));
+}
+
+// validation //////////////////////////////////////////////////////////////////
+
+#[test]
+fn mongodb_uri_is_required() {
+    assert_eq!(load("", &[]).unwrap_err(), ["mongodb_uri should be set, in the config file or as MONGODB_URI"]);
+}
+
+#[test]
+fn bind_address_needs_a_port() {
+    assert_eq!(problems("bind_address = \"localhost\"\n"), ["bind_address should be an address and port, like 0.0.0.0:8080, not 'localhost'"]);
+}
+
+#[test]
+fn workers_are_at_least_one() {
+    assert_eq!(problems("workers = 0\n"), ["workers should be at least 1"]);
+}
+
+#[test]
+fn pool_sizes_are_ordered() {
+    assert_eq!(problems("mongo_min_pool_size = 10\nmongo_max_pool_size = 5\n"), ["mongo_min_pool_size should be no more than mongo_max_pool_size"]);
+}
+
+#[test]
+fn page_size_is_within_max_page_size() {
+    assert_eq!(problems("page_size = 0\n"), ["page_size should be between 1 and max_page_size"]);
+    assert_eq!(problems("page_size = 20000\n"), ["page_size should be between 1 and max_page_size"]);
+}
+
+#[test]
+fn datasets_are_named_once() {
+    let datasets = "\
+        [[datasets]]\nname = \"bsose\"\ncollection = \"\"\nmetadata_collection = \"timeseriesMeta\"\ndata_type = \"BSOSE-profile\"\nschema = \"bsose\"\n\
+        [[datasets]]\nname = \"bsose\"\ncollection = \"bsose\"\nmetadata_collection = \"timeseriesMeta\"\ndata_type = \"BSOSE-profile\"\nschema = \"bsose\"\n";
+
+    assert_eq!(problems(datasets), ["every dataset should have a name and a collection", "dataset 'bsose' is defined more than once"]);
+}
+
+#[test]
+fn rate_limits_cover_the_anonymous_and_address_tiers() {
+    assert_eq!(problems("anonymous_tier = \"guest\"\naddress_tier = \"everyone\"\n"), [
+        "rate_limits should have an entry for the anonymous tier, 'guest'",
+        "rate_limits should have an entry for the address tier, 'everyone'",
+    ]);
+}
+
+#[test]
+fn rate_limits_allow_requests() {
+    let limits = "\
+        [rate_limits.anonymous]\nburst = 0.5\nper_second = 1.0\n\
+        [rate_limits.bulk]\nburst = 10.0\nper_second = 1.0\n";
+
+    assert_eq!(problems(limits), ["rate_limits.anonymous should allow a burst of at least 1 and a positive per_second"]);
+}
+
+#[test]
+fn log_filter_is_checked() {
+    let problems = problems("log_filter = \"info,[\"\n");
+
+    assert_eq!(problems.len(), 1);
+    assert!(problems[0].starts_with("log_filter isn't valid"));
+}
+
+#[test]
+fn every_problem_is_reported() {
+    assert_eq!(problems("workers = 0\npage_size = 0\n").len(), 2);
+}
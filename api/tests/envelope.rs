@@ -0,0 +1,104 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::TestApi;
+use serde_json::json;
+
+#[actix_web::test]
+async fn envelope_lifts_shared_metadata_out_of_each_document() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?pageSize=2&envelope=true&data=salinity&startDate=2020-09-15T00:00:00Z").await;
+    let envelope = response.json();
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(envelope["count"], 2);
+    assert_eq!(envelope["data_info"], json!([["salinity"], ["units"], [["psu"]]]));
+    assert_eq!(envelope["timeseries"], json!(["2020-09-15T12:26:40Z", "2020-09-16T12:26:40Z"]));
+    assert_eq!(envelope["data"][1]["data"], json!([[36.0, 37.0]]));
+    assert!(envelope["data"][0].get("data_info").is_none());
+    assert!(envelope["data"][0].get("timeseries").is_none());
+}
+
+#[actix_web::test]
+async fn envelope_echoes_the_normalized_query() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?box=[[370,0],[380,20]]&data=temperature,%3E3&envelope=true").await;
+
+    assert_eq!(response.json()["query"], json!({
+        "box": [[10.0, 0.0], [20.0, 20.0]],
+        "data": ["temperature"],
+        "dataConstraints": [["temperature", ">", 3.0]],
+    }));
+}
+
+#[actix_web::test]
+async fn envelope_carries_the_next_page_token() {
+    let api = TestApi::new().await;
+
+    let first = api.get("/search?pageSize=2&envelope=true").await;
+    let next = first.json()["next"].as_str().unwrap().to_string();
+    assert_eq!(first.header("x-next-page-token"), Some(next.clone()));
+
+    let last = api.get(&format!("/search?pageSize=2&envelope=true&pageToken={}", next)).await;
+    assert_eq!(last.json()["next"], json!(null));
+    assert_eq!(last.json()["count"], 1);
+}
+
+#[actix_web::test]
+async fn envelope_keeps_timeseries_per_document_after_value_constraints() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature,%3E3&envelope=true").await;
+
+    assert_eq!(response.json()["data"][0]["timeseries"], json!(["2020-09-16T12:26:40Z"]));
+}
+
+#[actix_web::test]
+async fn envelope_of_batchmeta_has_no_data_info_or_timeseries() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&batchmeta=true&envelope=true").await;
+    let envelope = response.json();
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(envelope["data_info"], json!(null));
+    assert_eq!(envelope["timeseries"], json!(null));
+    assert_eq!(envelope["count"], 1);
+    assert_eq!(envelope["data"][0]["_id"], "m1");
+    assert_eq!(envelope["data"][0]["data_info"], json!([["temperature", "salinity"], ["units"], [["degC"], ["psu"]]]));
+}
+
+#[actix_web::test]
+async fn envelope_wraps_argo_profiles() {
+    let api = TestApi::new().await;
+    let response = api.get("/argo?envelope=true&pageSize=1").await;
+    let envelope = response.json();
+
+    assert_eq!(envelope["count"], 1);
+    assert_eq!(envelope["data_info"], json!(null));
+    assert_eq!(envelope["data"][0]["_id"], "4902911_000");
+}
+
+#[actix_web::test]
+async fn envelope_and_batchmeta_are_true_or_false() {
+    let api = TestApi::new().await;
+
+    let plain = api.get("/search?id=a&envelope=false").await;
+    assert_eq!(plain.ids(), ["a"]);
+
+    let documents = api.get("/search?id=a&batchmeta=false").await;
+    assert_eq!(documents.ids(), ["a"]);
+
+    let metadata = api.get("/search?id=a&batchmeta=true").await;
+    assert_eq!(metadata.ids(), ["m1"]);
+
+    let neither = api.get("/search?envelope=yes&batchmeta=1").await;
+    assert_eq!(neither.status, StatusCode::BAD_REQUEST);
+    assert_eq!(neither.problems(), ["'envelope' should be true or false", "'batchmeta' should be true or false"]);
+}
+
+#[actix_web::test]
+async fn envelope_is_json_only() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?envelope=true&format=ndjson").await;
+
+    assert_eq!(response.problems(), ["'envelope' is only available with 'format=json'"]);
+}
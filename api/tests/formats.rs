@@ -0,0 +1,309 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use api::helpers::MemoryStore;
+use arrow::array::{Array, Float64Array};
+use arrow::ipc::reader::StreamReader;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use common::TestApi;
+use serde_json::json;
+
+// csv /////////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn csv_has_a_row_per_location_level_and_timestep() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=all&format=csv").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type").unwrap(), "text/csv; charset=utf-8");
+    assert_eq!(response.text().split_terminator("\r\n").collect::<Vec<_>>(), [
+        "_id,longitude,latitude,level,timestamp,temperature [degC],salinity [psu]",
+        "a,10,10,5,2020-09-13T12:26:40Z,1,30",
+        "a,10,10,5,2020-09-14T12:26:40Z,2,31",
+        "a,10,10,5,2020-09-15T12:26:40Z,3,36",
+        "a,10,10,5,2020-09-16T12:26:40Z,4,37",
+    ]);
+}
+
+#[actix_web::test]
+async fn csv_follows_the_date_window_and_variables() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?box=[[0,0],[20,20]]&data=salinity&startDate=2020-09-16T00:00:00Z&format=csv").await;
+
+    assert_eq!(response.text().split_terminator("\r\n").collect::<Vec<_>>(), [
+        "_id,longitude,latitude,level,timestamp,salinity [psu]",
+        "a,10,10,5,2020-09-16T12:26:40Z,37",
+        "c,11,10,5,2020-09-16T12:26:40Z,37",
+    ]);
+}
+
+#[actix_web::test]
+async fn csv_leaves_missing_values_empty() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/noaasst?id=s1&data=sst&format=csv").await;
+
+    assert_eq!(response.text().split_terminator("\r\n").nth(2), Some("s1,10.5,10.5,0,2020-09-14T12:26:40Z,"));
+}
+
+#[actix_web::test]
+async fn csv_cant_be_compressed() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?format=csv&compression=minimal").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn file_formats_with_no_results_are_not_found() {
+    let api = TestApi::new().await;
+    for format in ["csv", "netcdf", "arrow", "parquet"] {
+        let response = api.get(&format!("/search?box=[[100,0],[120,20]]&data=all&format={}", format)).await;
+
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "format={}", format);
+        assert_eq!(response.json()["code"], "not_found", "format={}", format);
+    }
+}
+
+// netcdf //////////////////////////////////////////////////////////////////////
+
+fn read_netcdf(body: &[u8]) -> netcdf3::FileReader {
+    netcdf3::FileReader::open_seek_read("search.nc", Box::new(std::io::Cursor::new(body.to_vec()))).expect("a readable netCDF file")
+}
+
+#[actix_web::test]
+async fn netcdf_round_trips_through_a_netcdf_reader() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?data=all&format=netcdf&startDate=2020-09-15T00:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type").unwrap(), "application/x-netcdf");
+
+    let mut file = read_netcdf(&response.body);
+    assert_eq!(file.version(), netcdf3::Version::Offset64Bit);
+
+    let data_set = file.data_set();
+    assert_eq!(data_set.get_global_attr_as_string("featureType").unwrap(), "timeSeries");
+    assert_eq!(data_set.dim_size("time"), Some(2));
+    assert_eq!(data_set.dim_size("location"), Some(3));
+    assert_eq!(data_set.get_unlimited_dim().map(|dim| dim.name()), Some(String::from("location")));
+    assert_eq!(data_set.get_var_attr_as_string("temperature", "units").unwrap(), "degC");
+
+    // 2020-09-15T12:26:40Z and a day later
+    assert_eq!(file.read_var_f64("time").unwrap(), [1_600_172_800.0, 1_600_259_200.0]);
+    assert_eq!(file.read_var_f64("longitude").unwrap(), [10.0, -170.0, 11.0]);
+    assert_eq!(file.read_var_f64("level").unwrap(), [5.0, 50.0, 5.0]);
+    // (location, time)
+    assert_eq!(file.read_var_f64("temperature").unwrap(), [3.0, 4.0, 3.0, 4.0, 3.0, 4.0]);
+    assert_eq!(file.read_var_f64("salinity").unwrap(), [36.0, 37.0, 36.0, 37.0, 36.0, 37.0]);
+}
+
+#[actix_web::test]
+async fn netcdf_links_each_location_to_its_record() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?data=temperature&format=netcdf").await;
+    let mut file = read_netcdf(&response.body);
+
+    assert_eq!(file.data_set().get_var_attr_as_string("station_id", "cf_role").unwrap(), "timeseries_id");
+    assert_eq!(file.data_set().dim_size("id_length"), Some(1));
+    assert_eq!(file.read_var_u8("station_id").unwrap(), b"abc");
+}
+
+#[actix_web::test]
+async fn netcdf_pads_station_ids_to_the_longest() {
+    let store = common::store().with_documents("bsose", vec![common::bsose_document("d-long", 12.0, 10.0, 5.0)]);
+    let api = TestApi::with(store, common::config()).await;
+    let response = api.get("/search?box=[[0,0],[20,20]]&data=temperature&format=netcdf").await;
+    let mut file = read_netcdf(&response.body);
+
+    assert_eq!(file.data_set().dim_size("id_length"), Some(6));
+    assert_eq!(file.read_var_u8("station_id").unwrap(), b"a\0\0\0\0\0c\0\0\0\0\0d-long");
+}
+
+#[actix_web::test]
+async fn netcdf_refuses_repeated_variables() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?data=temperature,temperature&format=netcdf").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.problems().len(), 1);
+}
+
+#[actix_web::test]
+async fn netcdf_refuses_metadata_listing_a_variable_twice() {
+    let mut metadata = common::bsose_metadata();
+    metadata.insert("data_info", mongodb::bson::bson!([["temperature", "temperature"], ["units"], [["degC"], ["degC"]]]));
+    let store = MemoryStore::new()
+        .with_documents("timeseriesMeta", vec![metadata])
+        .with_documents("bsose", vec![common::bsose_document("a", 10.0, 10.0, 5.0)]);
+    let mut config = common::config();
+    config.datasets.truncate(1);
+    let api = TestApi::with(store, config).await;
+
+    let response = api.get("/search?data=all&format=netcdf").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.problems()[0].starts_with("'temperature' appears more than once in 'data'"));
+
+    // other formats don't mind
+    assert_eq!(api.get("/search?data=all&format=csv").await.status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn netcdf_grows_with_the_page_not_the_grid() {
+    let api = TestApi::new().await;
+
+    // three documents over two levels and three locations; a dense cube would hold 2 x 3 cells per timestep
+    let response = api.get("/search?data=temperature&format=netcdf").await;
+    let mut file = read_netcdf(&response.body);
+
+    assert!(!file.data_set().has_dim("level"));
+    assert_eq!(file.read_var_f64("temperature").unwrap().len(), 4 * 3);
+}
+
+#[actix_web::test]
+async fn netcdf_fills_missing_values() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/noaasst?id=s1&data=sst&format=netcdf").await;
+    let mut file = read_netcdf(&response.body);
+
+    let sst = file.read_var_f64("sst").unwrap();
+    assert_eq!(sst.len(), 4);
+    assert_eq!(sst[1], netcdf3::NC_FILL_F64);
+    assert_eq!(file.data_set().get_var_attr_f64("sst", "_FillValue"), Some(&[netcdf3::NC_FILL_F64][..]));
+}
+
+#[actix_web::test]
+async fn netcdf_refuses_variables_named_like_coordinates() {
+    let mut metadata = common::bsose_metadata();
+    metadata.insert("data_info", mongodb::bson::bson!([["time", "salinity"], ["units"], [["s"], ["psu"]]]));
+    let store = MemoryStore::new()
+        .with_documents("timeseriesMeta", vec![metadata])
+        .with_documents("bsose", vec![common::bsose_document("a", 10.0, 10.0, 5.0)]);
+    let mut config = common::config();
+    config.datasets.truncate(1);
+    let api = TestApi::with(store, config).await;
+
+    let response = api.get("/search?data=time&format=netcdf").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.problems()[0].starts_with("'time' in 'data' can't be written to netCDF"));
+
+    let salinity = api.get("/search?data=salinity&format=netcdf").await;
+    assert_eq!(salinity.status, StatusCode::OK);
+}
+
+// geojson /////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn geojson_is_a_feature_collection_of_points() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&format=geojson").await;
+    let collection = response.json();
+    let feature = &collection["features"][0];
+
+    assert_eq!(response.header("content-type").unwrap(), "application/geo+json");
+    assert_eq!(collection["type"], "FeatureCollection");
+    assert_eq!(feature["id"], "a");
+    assert_eq!(feature["geometry"], json!({"type": "Point", "coordinates": [10.0, 10.0]}));
+    assert_eq!(feature["properties"]["data"], json!([[1.0, 2.0, 3.0, 4.0]]));
+    assert!(feature["properties"].get("geolocation").is_none());
+}
+
+#[actix_web::test]
+async fn geojson_stubs_keep_level_and_metadata() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?compression=minimal&format=geojson&id=b").await;
+
+    assert_eq!(response.json()["features"][0]["properties"], json!({"level": 50.0, "metadata": ["m1"]}));
+}
+
+#[actix_web::test]
+async fn geojson_serves_argo_profiles() {
+    let api = TestApi::new().await;
+    let response = api.get("/argo?id=4902911_000&format=geojson").await;
+    let feature = &response.json()["features"][0];
+
+    assert_eq!(feature["geometry"]["coordinates"], json!([10.0, 10.0]));
+    assert_eq!(feature["properties"]["timestamp"], "2020-09-13T12:26:40Z");
+}
+
+// ndjson //////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn ndjson_has_a_document_per_line() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?box=[[0,0],[20,20]]&data=salinity&format=ndjson").await;
+    let lines: Vec<serde_json::Value> = response.text().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+
+    assert_eq!(response.header("content-type").unwrap(), "application/x-ndjson");
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["_id"], "a");
+    assert_eq!(lines[1]["data"], json!([[30.0, 31.0, 36.0, 37.0]]));
+}
+
+#[actix_web::test]
+async fn ndjson_streams_stubs() {
+    let api = TestApi::new().await;
+    let response = api.get("/argo?compression=minimal&format=ndjson").await;
+
+    assert_eq!(response.text().lines().count(), 3);
+}
+
+#[actix_web::test]
+async fn streamed_formats_with_no_results_are_not_found() {
+    let api = TestApi::new().await;
+    for format in ["json", "ndjson"] {
+        let response = api.get(&format!("/search?box=[[100,0],[120,20]]&format={}", format)).await;
+
+        assert_eq!(response.status, StatusCode::NOT_FOUND, "format={}", format);
+    }
+}
+
+// arrow and parquet ///////////////////////////////////////////////////////////
+
+fn float_column(batch: &RecordBatch, name: &str) -> Vec<Option<f64>> {
+    batch.column_by_name(name).unwrap().as_any().downcast_ref::<Float64Array>().unwrap().iter().collect()
+}
+
+#[actix_web::test]
+async fn arrow_has_the_same_rows_as_csv() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=all&format=arrow&startDate=2020-09-15T00:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type").unwrap(), "application/vnd.apache.arrow.stream");
+
+    let batches: Vec<RecordBatch> = StreamReader::try_new(std::io::Cursor::new(response.body.to_vec()), None).unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    let batch = &batches[0];
+    let schema = batch.schema();
+
+    let names: Vec<&str> = schema.fields().iter().map(|field| field.name().as_str()).collect();
+    assert_eq!(names, ["_id", "longitude", "latitude", "level", "timestamp", "temperature", "salinity"]);
+    assert_eq!(schema.field_with_name("salinity").unwrap().metadata().get("units").map(String::as_str), Some("psu"));
+    assert_eq!(float_column(batch, "temperature"), [Some(3.0), Some(4.0)]);
+    assert_eq!(float_column(batch, "salinity"), [Some(36.0), Some(37.0)]);
+}
+
+#[actix_web::test]
+async fn parquet_keeps_missing_values_null() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/noaasst?id=s1&data=sst&format=parquet").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-disposition").unwrap(), "attachment; filename=\"noaasst.parquet\"");
+
+    let batches: Vec<RecordBatch> = ParquetRecordBatchReaderBuilder::try_new(response.body.clone()).unwrap().build().unwrap()
+        .collect::<Result<_, _>>().unwrap();
+    assert_eq!(float_column(&batches[0], "sst"), [Some(20.5), None, Some(21.0), Some(21.5)]);
+}
+
+#[actix_web::test]
+async fn file_formats_are_only_for_timeseries() {
+    let api = TestApi::new().await;
+    for format in ["csv", "netcdf", "arrow", "parquet"] {
+        let response = api.get(&format!("/argo?format={}", format)).await;
+
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "format={}", format);
+    }
+}
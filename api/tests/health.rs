@@ -0,0 +1,153 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::web;
+use api::helpers::*;
+use async_trait::async_trait;
+use common::TestApi;
+use mongodb::bson::{Bson, Document};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// the fixtures behind a database that can be taken down: every query fails while `down` is set
+struct FlakyStore {
+    store: MemoryStore,
+    down: Arc<AtomicBool>,
+}
+
+impl FlakyStore {
+    fn check(&self) -> Result<(), ApiError> {
+        match self.down.load(Ordering::SeqCst) {
+            true => Err(ApiError::Unavailable(String::from("database is down"))),
+            false => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl DataStore for FlakyStore {
+    async fn find(&self, collection: &str, filter: Document, options: QueryOptions) -> Result<DocumentStream, ApiError> {
+        self.check()?;
+        self.store.find(collection, filter, options).await
+    }
+
+    async fn estimated_count(&self, collection: &str) -> Result<u64, ApiError> {
+        self.check()?;
+        self.store.estimated_count(collection).await
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.check()?;
+        self.store.ping().await
+    }
+
+    async fn distinct(&self, collection: &str, field: &str, filter: Document) -> Result<Vec<Bson>, ApiError> {
+        self.check()?;
+        self.store.distinct(collection, field, filter).await
+    }
+}
+
+// an api over the fixtures with no datasets loaded yet, and the switch that takes its database down
+fn unloaded(down: bool) -> (TestApi, Arc<AtomicBool>) {
+    let down = Arc::new(AtomicBool::new(down));
+    let store = FlakyStore { store: common::store(), down: down.clone() };
+    let api = TestApi { state: web::Data::new(AppState::new(Arc::new(store), common::config())) };
+    (api, down)
+}
+
+// healthz /////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn healthz_answers_without_the_database() {
+    let (api, _) = unloaded(true);
+    let response = api.get("/healthz").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json(), json!({"status": "ok"}));
+}
+
+// readyz //////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn readyz_is_ready_once_every_dataset_is_loaded() {
+    let api = TestApi::new().await;
+    let response = api.get("/readyz").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json(), json!({"status": "ready", "database": "ok", "datasets": {"bsose": "loaded", "noaasst": "loaded"}}));
+}
+
+#[actix_web::test]
+async fn readyz_waits_for_datasets() {
+    let (api, _) = unloaded(false);
+    api.state.register(api.state.config.datasets[0].clone()).await.unwrap();
+    let response = api.get("/readyz").await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.json(), json!({"status": "not ready", "database": "ok", "datasets": {"bsose": "loaded", "noaasst": "loading"}}));
+}
+
+#[actix_web::test]
+async fn readyz_needs_the_database() {
+    let (api, down) = unloaded(false);
+    api.state.load_datasets().await;
+    down.store(true, Ordering::SeqCst);
+    let response = api.get("/readyz").await;
+
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.json()["status"], "not ready");
+    assert_eq!(response.json()["database"], "unreachable");
+    assert_eq!(response.json()["datasets"], json!({"bsose": "loaded", "noaasst": "loaded"}));
+}
+
+// loading /////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn searches_are_unavailable_until_loading_retries_succeed() {
+    let (api, down) = unloaded(true);
+    let loader = api.state.clone();
+    actix_web::rt::spawn(async move { loader.load_datasets().await });
+    // long enough for the first attempt to fail
+    actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+
+    let loading = api.get("/timeseries/bsose?id=a").await;
+    assert_eq!(loading.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(loading.json()["message"], "Dataset 'bsose' is still loading; try again shortly");
+    assert_eq!(api.get("/readyz").await.json()["datasets"], json!({"bsose": "loading", "noaasst": "loading"}));
+
+    // the loader retries a second after its first attempt
+    down.store(false, Ordering::SeqCst);
+    for _ in 0..50 {
+        if api.state.is_loaded("bsose") && api.state.is_loaded("noaasst") {
+            break;
+        }
+        actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(api.get("/readyz").await.status, StatusCode::OK);
+    let loaded = api.get("/timeseries/bsose?id=a").await;
+    assert_eq!(loaded.status, StatusCode::OK);
+    assert_eq!(loaded.ids(), ["a"]);
+}
+
+// version /////////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn version_reports_the_build_and_dataset_freshness() {
+    let (api, _) = unloaded(false);
+    api.state.register(api.state.config.datasets[0].clone()).await.unwrap();
+    let response = api.get("/version").await;
+    let body = response.json();
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(body["name"], "api");
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    // null unless GIT_COMMIT was set for the build
+    assert_eq!(body["git_commit"], json!(option_env!("GIT_COMMIT")));
+    assert_eq!(body["datasets"], json!({
+        "bsose": {"date_updated_argovis": "2020-09-13T12:26:40Z"},
+        "noaasst": {"date_updated_argovis": null},
+    }));
+}
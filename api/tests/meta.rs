@@ -0,0 +1,118 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::TestApi;
+use serde_json::json;
+
+// at most two metadata ids, or two grid cells behind a region, per request
+fn small_pages() -> api::helpers::Config {
+    let mut config = common::config();
+    config.max_page_size = 2;
+    config
+}
+
+// metadata //////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn meta_by_id() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/bsose/meta?id=m1").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["m1"]);
+    assert_eq!(response.json()[0]["data_info"][0], json!(["temperature", "salinity"]));
+}
+
+#[actix_web::test]
+async fn meta_by_region() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/bsose/meta?box=[[0,0],[20,20]]").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["m1"]);
+}
+
+#[actix_web::test]
+async fn meta_region_with_no_grid_cells_is_not_found() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/bsose/meta?box=[[100,0],[120,20]]").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn meta_is_only_served_for_its_own_dataset() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/noaasst/meta?id=m1").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn meta_needs_exactly_one_of_id_or_a_region() {
+    let api = TestApi::new().await;
+
+    let neither = api.get("/timeseries/bsose/meta").await;
+    assert_eq!(neither.status, StatusCode::BAD_REQUEST);
+    assert_eq!(neither.problems(), ["Exactly one of 'id', 'polygon', 'box', or 'center' should be defined"]);
+
+    let both = api.get("/timeseries/bsose/meta?id=m1&box=[[0,0],[20,20]]").await;
+    assert_eq!(both.status, StatusCode::BAD_REQUEST);
+
+    let empty_id = api.get("/timeseries/bsose/meta?id=m1,").await;
+    assert_eq!(empty_id.problems(), ["'id' should be a comma separated list of metadata ids, like id=a,b,c"]);
+}
+
+#[actix_web::test]
+async fn meta_takes_no_data_parameters() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/bsose/meta?id=m1&data=all").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.problems()[0].starts_with("'data' is not a recognized query parameter"));
+}
+
+#[actix_web::test]
+async fn meta_caps_the_ids_asked_for() {
+    let api = TestApi::with(common::store(), small_pages()).await;
+
+    assert_eq!(api.get("/timeseries/bsose/meta?id=m1,m2").await.status, StatusCode::OK);
+
+    let response = api.get("/timeseries/bsose/meta?id=m1,m2,m3").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.problems(), ["'id' should list at most 2 metadata ids"]);
+}
+
+#[actix_web::test]
+async fn meta_caps_the_grid_cells_a_region_reads() {
+    let api = TestApi::with(common::store(), small_pages()).await;
+
+    assert_eq!(api.get("/timeseries/bsose/meta?box=[[0,0],[20,20]]").await.status, StatusCode::OK);
+
+    let response = api.get("/timeseries/bsose/meta?box=[[-180,-90],[180,90]]").await;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(response.json()["message"].as_str().unwrap().contains("more than 2 grid cells"));
+}
+
+// summary ///////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn summary_describes_the_dataset_as_loaded() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/bsose/summary").await;
+    let summary = response.json();
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(summary["dataset"], "bsose");
+    assert_eq!(summary["data_type"], "BSOSE-profile");
+    assert_eq!(summary["timeseries"], json!({"start": "2020-09-13T12:26:40Z", "end": "2020-09-16T12:26:40Z", "steps": 4}));
+    assert_eq!(summary["document_count"], 3);
+}
+
+#[actix_web::test]
+async fn summary_of_an_unknown_dataset_is_not_found() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/nope/summary").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
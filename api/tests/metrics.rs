@@ -0,0 +1,49 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::TestApi;
+
+// the value of one sample in the /metrics text, like argovis_validation_failures_total{rule="box"}
+async fn sample(api: &TestApi, name: &str) -> Option<f64> {
+    let response = api.get("/metrics").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    response.text().lines()
+        .find_map(|line| line.strip_prefix(name).and_then(|rest| rest.strip_prefix(' ')))
+        .map(|value| value.parse().unwrap())
+}
+
+#[actix_web::test]
+async fn validation_failures_are_counted_by_rule() {
+    let api = TestApi::new().await;
+    api.get("/search?box=oops&startDate=2020-09-15T00:00:00Z&endDate=2020-09-14T00:00:00Z&colour=blue&page=1&page=2").await;
+    api.get("/search?box=[[0,0]]").await;
+
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="box"}"#).await, Some(2.0));
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="date_order"}"#).await, Some(1.0));
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="unrecognized_parameter"}"#).await, Some(1.0));
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="duplicate_parameter"}"#).await, Some(1.0));
+}
+
+#[actix_web::test]
+async fn validation_rules_dont_depend_on_the_message() {
+    let api = TestApi::new().await;
+    api.get("/search?data=nope").await;
+    api.get("/search?startDate=2021-01-01T00:00:00Z").await;
+    api.get("/timeseries/bsose/vocabulary?parameter=colour").await;
+
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="data"}"#).await, Some(1.0));
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="date_window"}"#).await, Some(1.0));
+    assert_eq!(sample(&api, r#"argovis_validation_failures_total{rule="parameter"}"#).await, Some(1.0));
+}
+
+#[actix_web::test]
+async fn requests_are_counted_by_route_and_status() {
+    let api = TestApi::new().await;
+    api.get("/search?id=a").await;
+    api.get("/search?id=a").await;
+
+    let metrics = api.get("/metrics").await.text();
+    assert!(metrics.lines().any(|line| line.starts_with("argovis_http_requests_total{") && line.contains(r#"status="200""#) && line.ends_with(" 2")));
+    assert!(metrics.contains("argovis_db_query_duration_seconds_count{collection=\"bsose\"}"));
+}
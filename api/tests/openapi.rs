@@ -0,0 +1,142 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use api::helpers::params::{self, MetaQuery, SearchParams, SearchQuery, VocabularyQuery, META_PARAMETERS, SEARCH_PARAMETERS};
+use common::TestApi;
+use serde_json::{json, Value};
+use utoipa::IntoParams;
+
+fn names<T: IntoParams>() -> Vec<String> {
+    T::into_params(|| None).into_iter().map(|parameter| parameter.name).collect()
+}
+
+// the served document's query parameters for `path`, with $ref schemas replaced by the component they name
+fn documented_parameters(document: &Value, path: &str) -> Vec<Value> {
+    document["paths"][path]["get"]["parameters"].as_array().unwrap().iter()
+        .filter(|parameter| parameter["in"] == "query")
+        .map(|parameter| {
+            let mut parameter = parameter.clone();
+            if let Some(reference) = parameter["schema"]["$ref"].as_str() {
+                let name = reference.trim_start_matches("#/components/schemas/");
+                parameter["schema"] = document["components"]["schemas"][name].clone();
+            }
+            parameter
+        })
+        .collect()
+}
+
+// a value of the documented type and one of another, for types the parser checks; plain strings are free text
+fn samples(schema: &Value) -> Option<(String, String)> {
+    if let Some(values) = schema["enum"].as_array() {
+        return Some((values[0].as_str().unwrap().to_string(), String::from("nope")));
+    }
+    let (valid, invalid) = match (schema["type"].as_str().unwrap(), schema["format"].as_str()) {
+        ("integer", _) => ("2", "2.5"),
+        ("number", _) => ("1.5", "wide"),
+        ("boolean", _) => ("true", "maybe"),
+        ("string", Some("date-time")) => ("2020-09-14T00:00:00Z", "yesterday"),
+        _ => return None,
+    };
+    Some((valid.to_string(), invalid.to_string()))
+}
+
+// the rules `parameter` alone, set to `value`, breaks
+fn broken_rules(parameter: &str, value: &str) -> Vec<&'static str> {
+    match SearchParams::from_pairs(vec![(parameter.to_string(), value.to_string())]) {
+        Ok(_) => Vec::new(),
+        Err(problems) => problems.into_iter().map(|problem| problem.rule).collect(),
+    }
+}
+
+// the query parameters the served document lists for `path`
+fn documented(document: &Value, path: &str) -> Vec<String> {
+    document["paths"][path]["get"]["parameters"].as_array().unwrap().iter()
+        .filter(|parameter| parameter["in"] == "query")
+        .map(|parameter| parameter["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn search_query_documents_every_search_parameter() {
+    assert_eq!(names::<SearchQuery>(), SEARCH_PARAMETERS);
+}
+
+#[test]
+fn meta_query_documents_every_meta_parameter() {
+    assert_eq!(names::<MetaQuery>(), META_PARAMETERS);
+}
+
+#[test]
+fn vocabulary_query_documents_its_one_parameter() {
+    assert_eq!(names::<VocabularyQuery>(), ["parameter"]);
+}
+
+#[actix_web::test]
+async fn served_document_lists_the_parameters_handlers_accept() {
+    let api = TestApi::new().await;
+    let response = api.get("/openapi.json").await;
+    let document = response.json();
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(documented(&document, "/search"), SEARCH_PARAMETERS);
+    assert_eq!(documented(&document, "/timeseries/{dataset}"), SEARCH_PARAMETERS);
+    assert_eq!(documented(&document, "/argo"), SEARCH_PARAMETERS);
+    assert_eq!(documented(&document, "/timeseries/{dataset}/meta"), META_PARAMETERS);
+}
+
+#[actix_web::test]
+async fn documented_search_parameter_types_are_the_ones_parsed() {
+    let api = TestApi::new().await;
+    let document = api.get("/openapi.json").await.json();
+
+    let mut checked = Vec::new();
+    for parameter in documented_parameters(&document, "/search") {
+        let name = parameter["name"].as_str().unwrap();
+        assert_eq!(parameter["required"], false, "{} is documented as required", name);
+
+        if let Some((valid, invalid)) = samples(&parameter["schema"]) {
+            assert!(!broken_rules(name, &valid).contains(&name), "{}={} is documented but refused", name, valid);
+            assert!(broken_rules(name, &invalid).contains(&name), "{}={} is accepted but not documented", name, invalid);
+            checked.push(name.to_string());
+        }
+    }
+    assert_eq!(checked, [
+        "radius", "startDate", "endDate", "mostrecent", "compression", "batchmeta", "page", "pageSize", "format", "envelope",
+    ]);
+    assert!(SearchParams::from_pairs(Vec::new()).is_ok());
+}
+
+#[actix_web::test]
+async fn documented_enums_are_every_value_parsed() {
+    let api = TestApi::new().await;
+    let document = api.get("/openapi.json").await.json();
+    let parameters = documented_parameters(&document, "/search");
+    let values = |name: &str| parameters.iter().find(|parameter| parameter["name"] == name).unwrap()["schema"]["enum"].clone();
+
+    assert_eq!(values("format"), json!(params::FORMATS));
+    assert_eq!(values("compression"), json!(["minimal"]));
+    for format in params::FORMATS {
+        assert!(!broken_rules("format", format).contains(&"format"), "format={} is documented but refused", format);
+    }
+}
+
+#[actix_web::test]
+async fn documented_meta_and_vocabulary_parameters_are_required_as_parsed() {
+    let api = TestApi::new().await;
+    let document = api.get("/openapi.json").await.json();
+
+    for parameter in documented_parameters(&document, "/timeseries/{dataset}/meta") {
+        assert_eq!(parameter["required"], false);
+        let name = parameter["name"].as_str().unwrap();
+        match samples(&parameter["schema"]) {
+            Some((_, invalid)) => assert!(broken_rules(name, &invalid).contains(&name)),
+            None => assert_eq!(parameter["schema"]["type"], "string"),
+        }
+    }
+
+    let vocabulary = documented_parameters(&document, "/timeseries/{dataset}/vocabulary");
+    assert_eq!(vocabulary.len(), 1);
+    assert_eq!(vocabulary[0]["required"], true);
+    assert_eq!(vocabulary[0]["schema"]["type"], "string");
+    assert!(params::vocabulary_parameter(Vec::new()).is_err());
+}
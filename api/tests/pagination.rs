@@ -0,0 +1,168 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use api::helpers::*;
+use common::TestApi;
+use futures::TryStreamExt;
+use mongodb::bson::{doc, Document};
+
+const NEXT: &str = "x-next-page-token";
+
+#[actix_web::test]
+async fn pages_follow_next_page_tokens() {
+    let api = TestApi::new().await;
+
+    let first = api.get("/search?pageSize=2").await;
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(first.ids(), ["a", "b"]);
+    let token = first.header(NEXT).expect("a token for the second page");
+
+    let second = api.get(&format!("/search?pageSize=2&pageToken={}", token)).await;
+    assert_eq!(second.ids(), ["c"]);
+    assert_eq!(second.header(NEXT), None);
+}
+
+#[actix_web::test]
+async fn last_page_has_no_token() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?pageSize=3").await;
+
+    assert_eq!(response.ids(), ["a", "b", "c"]);
+    assert_eq!(response.header(NEXT), None);
+}
+
+#[actix_web::test]
+async fn tokens_carry_the_filter_through() {
+    let api = TestApi::new().await;
+
+    let first = api.get("/argo?box=[[10.5,0],[20,20]]&pageSize=1").await;
+    assert_eq!(first.ids(), ["4902911_001"]);
+
+    let token = first.header(NEXT).unwrap();
+    let second = api.get(&format!("/argo?box=[[10.5,0],[20,20]]&pageSize=1&pageToken={}", token)).await;
+    assert_eq!(second.ids(), ["4902911_002"]);
+    assert_eq!(second.header(NEXT), None);
+}
+
+#[actix_web::test]
+async fn legacy_page_skips_whole_pages() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?page=1&pageSize=2").await;
+
+    assert_eq!(response.ids(), ["c"]);
+}
+
+#[actix_web::test]
+async fn page_past_the_end_is_not_found() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?page=5&pageSize=2").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn huge_page_is_rejected_rather_than_overflowing() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?page=9223372036854775807&pageSize=2").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.problems()[0].starts_with("'page' times 'pageSize' is too large"));
+}
+
+#[actix_web::test]
+async fn page_size_is_bounded() {
+    let api = TestApi::new().await;
+
+    let too_big = api.get("/search?pageSize=10001").await;
+    assert_eq!(too_big.status, StatusCode::BAD_REQUEST);
+    assert_eq!(too_big.problems(), ["'pageSize' should be at most 10000"]);
+
+    let zero = api.get("/search?pageSize=0").await;
+    assert_eq!(zero.problems(), ["'pageSize' should be a positive integer"]);
+}
+
+#[actix_web::test]
+async fn tokens_must_come_from_a_search() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?pageToken=not-a-token").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.problems(), ["'pageToken' should be a token returned by a previous search"]);
+}
+
+#[actix_web::test]
+async fn page_and_token_cant_be_combined() {
+    let api = TestApi::new().await;
+    let token = api.get("/search?pageSize=1").await.header(NEXT).unwrap();
+    let response = api.get(&format!("/search?page=1&pageToken={}", token)).await;
+
+    assert_eq!(response.problems(), ["'page' and 'pageToken' can't both be defined"]);
+}
+
+// eight profiles stored out of _id order, five of them in the box [[12.5,0],[20,20]]
+fn profiles() -> MemoryStore {
+    let documents: Vec<Document> = [5, 0, 7, 2, 6, 1, 4, 3].iter()
+        .map(|&i| {
+            let mut document = common::argo_document(i);
+            document.insert("_id", format!("p{}", i));
+            document
+        })
+        .collect();
+    MemoryStore::new().with_documents("argo", documents)
+}
+
+async fn ids(store: &MemoryStore, filter: Document, options: QueryOptions) -> Vec<String> {
+    store.find("argo", filter, options).await.unwrap()
+        .map_ok(|document| document.get_str("_id").unwrap().to_string())
+        .try_collect()
+        .await
+        .unwrap()
+}
+
+// every page found from the _ids alone, read back in full by its filter as the handlers do, following tokens to the end;
+// a legacy 'page' only picks the first
+async fn pages(store: &MemoryStore, query: &[(&str, &str)]) -> Vec<Vec<String>> {
+    let config = common::config();
+    let mut pages = Vec::new();
+    let mut token: Option<String> = None;
+    loop {
+        let mut pairs: Vec<(String, String)> = query.iter()
+            .filter(|(k, _)| token.is_none() || *k != "page")
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        pairs.extend(token.take().map(|token| (String::from("pageToken"), token)));
+        let params = SearchParams::from_pairs(pairs).unwrap();
+        let filter = filters::filter_points(&params).unwrap();
+
+        let Some(page) = pagination::page(store, "argo", filter, &params, &config).await.unwrap() else {
+            return pages;
+        };
+        let options = QueryOptions { sort: Some((String::from("_id"), 1)), ..QueryOptions::default() };
+        pages.push(ids(store, page.filter, options).await);
+        match page.next {
+            Some(next) => token = Some(next),
+            None => return pages,
+        }
+    }
+}
+
+#[actix_web::test]
+async fn page_filters_read_back_exactly_the_ids_paginated() {
+    let store = profiles();
+    let everything = ids(&store, doc! {}, QueryOptions { sort: Some((String::from("_id"), 1)), ..QueryOptions::default() }).await;
+
+    for page_size in ["1", "3", "8", "10"] {
+        let pages = pages(&store, &[("pageSize", page_size)]).await;
+        assert!(pages.iter().all(|page| page.len() <= page_size.parse::<usize>().unwrap()));
+        assert_eq!(pages.concat(), everything, "pageSize={}", page_size);
+    }
+
+    let boxed = pages(&store, &[("pageSize", "2"), ("box", "[[12.5,0],[20,20]]")]).await;
+    assert_eq!(boxed, [vec!["p3", "p4"], vec!["p5", "p6"], vec!["p7"]]);
+}
+
+#[actix_web::test]
+async fn legacy_page_filters_read_back_exactly_the_ids_skipped_to() {
+    let store = profiles();
+    assert_eq!(pages(&store, &[("pageSize", "3"), ("page", "1")]).await, [vec!["p3", "p4", "p5"], vec!["p6", "p7"]]);
+}
@@ -0,0 +1,42 @@
+use api::helpers::errors::Problem;
+use api::helpers::params::{Comparison, SearchParams, ValueConstraint};
+
+fn parse(pairs: &[(&str, &str)]) -> Result<SearchParams, Vec<Problem>> {
+    SearchParams::from_pairs(pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect())
+}
+
+// data ////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn data_lists_variables_in_order() {
+    let params = parse(&[("data", "salinity,temperature")]).unwrap();
+
+    assert_eq!(params.data, ["salinity", "temperature"]);
+    assert!(params.data_constraints.is_empty());
+}
+
+#[test]
+fn data_bounds_follow_their_variable() {
+    let params = parse(&[("data", "temperature,>=2,<4,salinity")]).unwrap();
+
+    assert_eq!(params.data, ["temperature", "salinity"]);
+    assert_eq!(params.data_constraints, [
+        ValueConstraint { variable: String::from("temperature"), comparison: Comparison::GreaterOrEqual, value: 2.0 },
+        ValueConstraint { variable: String::from("temperature"), comparison: Comparison::Less, value: 4.0 },
+    ]);
+}
+
+#[test]
+fn data_rejects_repeated_variables() {
+    let problems = parse(&[("data", "temperature,salinity,temperature")]).unwrap_err();
+
+    assert_eq!(problems, [Problem::new("data", "'temperature' is listed more than once in 'data'; put all its bounds after one mention, like temperature,>10,<20")]);
+}
+
+#[test]
+fn data_rejects_a_variable_repeated_to_add_bounds() {
+    let problems = parse(&[("data", "temperature,>1,temperature,<5")]).unwrap_err();
+
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].rule, "data");
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use api::helpers::*;
+use api::helpers::ratelimit::{CachedKeys, FileKeys, KeyStore, RateLimiter};
+use async_trait::async_trait;
+use common::TestApi;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+// limits that run out within a test and barely refill during it
+fn config(anonymous: f64, standard: f64, address: f64) -> Config {
+    let mut config = common::config();
+    config.rate_limits = HashMap::from([
+        (String::from("anonymous"), RateLimit { burst: anonymous, per_second: 0.001 }),
+        (String::from("standard"), RateLimit { burst: standard, per_second: 0.001 }),
+        (String::from("address"), RateLimit { burst: address, per_second: 0.001 }),
+    ]);
+    config.address_tier = String::from("address");
+    config
+}
+
+async fn get_from(api: &TestApi, uri: &str, address: &str, key: Option<&str>) -> StatusCode {
+    let mut request = TestRequest::get().uri(uri).peer_addr(address.parse().unwrap());
+    if let Some(key) = key {
+        request = request.insert_header(("x-argokey", key));
+    }
+    api.call(request).await.status
+}
+
+// counts lookups that reach it
+struct CountingKeys {
+    keys: FileKeys,
+    lookups: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl KeyStore for CountingKeys {
+    async fn tier(&self, key: &str) -> Result<Option<String>, ApiError> {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+        self.keys.tier(key).await
+    }
+}
+
+// middleware //////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn anonymous_requests_are_limited_per_address() {
+    let api = TestApi::with(common::store(), config(2.0, 10.0, 10.0)).await;
+
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", None).await, StatusCode::OK);
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", None).await, StatusCode::OK);
+
+    let limited = api.call(TestRequest::get().uri("/search?id=a").peer_addr("10.0.0.1:1".parse().unwrap())).await;
+    assert_eq!(limited.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(limited.json()["code"], "rate_limited");
+    assert!(limited.header("retry-after").is_some());
+
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.2:1", None).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn keys_draw_on_their_own_tier() {
+    let api = TestApi::with(common::store(), config(1.0, 3.0, 10.0)).await;
+
+    for _ in 0..3 {
+        assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("k1")).await, StatusCode::OK);
+    }
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("k1")).await, StatusCode::TOO_MANY_REQUESTS);
+
+    // the key's bucket follows it to other addresses, and doesn't use up its address's anonymous bucket
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.2:1", Some("k1")).await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", None).await, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn keyed_requests_are_also_limited_per_address() {
+    let api = TestApi::with(common::store(), config(1.0, 10.0, 2.0)).await;
+
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("k1")).await, StatusCode::OK);
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("k1")).await, StatusCode::OK);
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("k1")).await, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn unknown_keys_are_unauthorized_and_still_charged() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let keys = CountingKeys { keys: FileKeys::new(HashMap::from([(String::from("k1"), String::from("standard"))])), lookups: lookups.clone() };
+    let state = AppState::new(Arc::new(common::store()), config(10.0, 10.0, 2.0)).with_keys(Arc::new(keys));
+    let api = TestApi::serve(state).await;
+
+    let response = api.call(TestRequest::get().uri("/search?id=a").peer_addr("10.0.0.1:1".parse().unwrap()).insert_header(("x-argokey", "nope"))).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.json()["code"], "unauthorized");
+
+    // a new bogus key per request doesn't get past the address's bucket, or reach the key store once it's empty
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("nope2")).await, StatusCode::UNAUTHORIZED);
+    assert_eq!(get_from(&api, "/search?id=a", "10.0.0.1:1", Some("nope3")).await, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn probes_are_never_limited() {
+    let api = TestApi::with(common::store(), config(1.0, 1.0, 1.0)).await;
+
+    for _ in 0..3 {
+        assert_eq!(get_from(&api, "/healthz", "10.0.0.1:1", Some("nope")).await, StatusCode::OK);
+    }
+}
+
+// key store ///////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn cached_keys_remember_tiers_and_misses() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let keys = CountingKeys { keys: FileKeys::new(HashMap::from([(String::from("k1"), String::from("standard"))])), lookups: lookups.clone() };
+    let cached = CachedKeys::new(keys, Duration::from_secs(60));
+
+    for _ in 0..3 {
+        assert_eq!(cached.tier("k1").await.unwrap(), Some(String::from("standard")));
+        assert_eq!(cached.tier("nope").await.unwrap(), None);
+    }
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn cached_keys_ask_again_once_stale() {
+    let lookups = Arc::new(AtomicUsize::new(0));
+    let keys = CountingKeys { keys: FileKeys::default(), lookups: lookups.clone() };
+    let cached = CachedKeys::new(keys, Duration::ZERO);
+
+    cached.tier("nope").await.unwrap();
+    cached.tier("nope").await.unwrap();
+    assert_eq!(lookups.load(Ordering::SeqCst), 2);
+}
+
+#[actix_web::test]
+async fn collection_keys_are_read_from_the_store() {
+    let api = TestApi::new().await;
+
+    assert_eq!(api.state.keys.tier("k1").await.unwrap(), Some(String::from("standard")));
+    assert_eq!(api.state.keys.tier("nope").await.unwrap(), None);
+}
+
+// token buckets ///////////////////////////////////////////////////////////////
+
+#[test]
+fn buckets_are_pruned_against_their_own_limits() {
+    let limiter = RateLimiter::new();
+    let small = RateLimit { burst: 1.0, per_second: 1000.0 };
+    let large = RateLimit { burst: 3.0, per_second: 0.001 };
+
+    limiter.take("small", &small).unwrap();
+    limiter.take("large", &large).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    limiter.prune();
+
+    // small has refilled and is forgotten; large is two tokens short of its burst and is kept
+    assert_eq!(limiter.len(), 1);
+    limiter.take("large", &large).unwrap();
+    limiter.take("large", &large).unwrap();
+    assert!(limiter.take("large", &large).is_err());
+}
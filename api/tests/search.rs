@@ -0,0 +1,388 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use common::TestApi;
+use serde_json::json;
+
+// spatial and vertical filters ////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn search_without_filters_returns_every_document() {
+    let api = TestApi::new().await;
+    let response = api.get("/search").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["a", "b", "c"]);
+}
+
+#[actix_web::test]
+async fn search_by_id() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=b").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["b"]);
+}
+
+#[actix_web::test]
+async fn search_by_box() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?box=[[0,0],[20,20]]").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["a", "c"]);
+}
+
+#[actix_web::test]
+async fn search_by_polygon() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?polygon=[[-180,-20],[-160,-20],[-160,0],[-180,0],[-180,-20]]").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["b"]);
+}
+
+#[actix_web::test]
+async fn search_by_center_and_radius() {
+    let api = TestApi::new().await;
+
+    // c is about 110km east of a
+    let near = api.get("/search?center=[10,10]&radius=1000").await;
+    assert_eq!(near.ids(), ["a"]);
+
+    let wider = api.get("/search?center=[10,10]&radius=200000").await;
+    assert_eq!(wider.ids(), ["a", "c"]);
+}
+
+#[actix_web::test]
+async fn search_by_vertical_range() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?verticalRange=[10,100]").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["b"]);
+}
+
+#[actix_web::test]
+async fn vertical_range_must_be_ordered() {
+    let api = TestApi::new().await;
+
+    for range in ["[100,10]", "[10,10]"] {
+        let response = api.get(&format!("/search?verticalRange={}", range)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.problems(), ["'verticalRange' should have its lower bound before its upper bound"]);
+    }
+}
+
+#[actix_web::test]
+async fn search_with_no_matches_is_not_found() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?box=[[100,0],[120,20]]").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.json()["code"], "not_found");
+}
+
+// variable selection //////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn search_omits_data_unless_asked_for() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a").await;
+
+    assert_eq!(response.json()[0]["data"], json!([]));
+}
+
+#[actix_web::test]
+async fn search_selects_variables() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=salinity").await;
+    let document = &response.json()[0];
+
+    assert_eq!(document["data"], json!([[30.0, 31.0, 36.0, 37.0]]));
+    assert_eq!(document["data_info"][0], json!(["salinity"]));
+}
+
+#[actix_web::test]
+async fn search_returns_all_variables() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=all").await;
+
+    assert_eq!(response.json()[0]["data"], json!([[1.0, 2.0, 3.0, 4.0], [30.0, 31.0, 36.0, 37.0]]));
+}
+
+// validation //////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn search_rejects_unknown_parameters() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?colour=blue").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json()["code"], "validation_error");
+    assert!(response.problems()[0].starts_with("'colour' is not a recognized query parameter"));
+}
+
+#[actix_web::test]
+async fn search_reports_every_problem() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?center=[10,10]&box=[[0,0],[1,1]]&polygon=oops").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.problems().len(), 3);
+}
+
+#[actix_web::test]
+async fn timeseries_dataset_must_be_configured() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/nope").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert!(response.json()["message"].as_str().unwrap().contains("available datasets are bsose, noaasst"));
+}
+
+#[actix_web::test]
+async fn parameters_are_checked_before_the_dataset() {
+    let api = TestApi::new().await;
+
+    for uri in ["/search?colour=blue", "/timeseries/nope?colour=blue", "/timeseries/nope/meta?colour=blue", "/timeseries/nope/vocabulary?colour=blue"] {
+        assert_eq!(api.get(uri).await.status, StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn timeseries_serves_the_same_documents_as_search() {
+    let api = TestApi::new().await;
+    let search = api.get("/search?box=[[0,0],[20,20]]&data=all").await;
+    let timeseries = api.get("/timeseries/bsose?box=[[0,0],[20,20]]&data=all").await;
+
+    assert_eq!(search.json(), timeseries.json());
+}
+
+// gridded products ////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn grid_search_returns_missing_values_as_null() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/noaasst?box=[[0,0],[20,20]]&data=sst").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["s1"]);
+    assert_eq!(response.json()[0]["data"], json!([[20.5, null, 21.0, 21.5]]));
+}
+
+// date windows ////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn date_window_slices_the_timeseries() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&startDate=2020-09-14T00:00:00Z&endDate=2020-09-16T00:00:00Z").await;
+    let document = &response.json()[0];
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(document["data"], json!([[2.0, 3.0]]));
+    assert_eq!(document["timeseries"], json!(["2020-09-14T12:26:40Z", "2020-09-15T12:26:40Z"]));
+}
+
+#[actix_web::test]
+async fn date_window_is_closed_at_the_start_and_open_at_the_end() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&startDate=2020-09-14T12:26:40Z&endDate=2020-09-15T12:26:40Z").await;
+
+    assert_eq!(response.json()[0]["data"], json!([[2.0]]));
+}
+
+#[actix_web::test]
+async fn date_window_between_timesteps_is_not_found() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&startDate=2020-09-14T13:00:00Z&endDate=2020-09-14T14:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn date_window_outside_the_timeseries_is_rejected() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?startDate=2021-01-01T00:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert!(response.text().contains("runs from 2020-09-13T12:26:40Z to 2020-09-16T12:26:40Z"));
+}
+
+#[actix_web::test]
+async fn date_window_must_be_ordered() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?startDate=2020-09-15T00:00:00Z&endDate=2020-09-14T00:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.problems(), ["'startDate' should be before 'endDate'"]);
+}
+
+#[actix_web::test]
+async fn argo_date_window_selects_profiles() {
+    let api = TestApi::new().await;
+    let response = api.get("/argo?startDate=2020-09-14T00:00:00Z&endDate=2020-09-16T00:00:00Z").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["4902911_001", "4902911_002"]);
+}
+
+// most recent ///////////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn most_recent_keeps_the_last_timesteps() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&mostrecent=2").await;
+    let document = &response.json()[0];
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(document["data"], json!([[3.0, 4.0]]));
+    assert_eq!(document["timeseries"], json!(["2020-09-15T12:26:40Z", "2020-09-16T12:26:40Z"]));
+}
+
+#[actix_web::test]
+async fn most_recent_counts_back_from_the_end_of_the_date_window() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature&startDate=2020-09-13T00:00:00Z&endDate=2020-09-16T00:00:00Z&mostrecent=2").await;
+
+    assert_eq!(response.json()[0]["data"], json!([[2.0, 3.0]]));
+}
+
+#[actix_web::test]
+async fn most_recent_larger_than_the_window_keeps_all_of_it() {
+    let api = TestApi::new().await;
+
+    let window = api.get("/search?id=a&data=temperature&startDate=2020-09-14T00:00:00Z&endDate=2020-09-16T00:00:00Z&mostrecent=10").await;
+    assert_eq!(window.json()[0]["data"], json!([[2.0, 3.0]]));
+
+    let everything = api.get("/search?id=a&data=temperature&mostrecent=10").await;
+    assert_eq!(everything.json()[0]["data"], json!([[1.0, 2.0, 3.0, 4.0]]));
+}
+
+#[actix_web::test]
+async fn most_recent_must_be_positive() {
+    let api = TestApi::new().await;
+
+    for most_recent in ["0", "-1", "two"] {
+        let response = api.get(&format!("/search?mostrecent={}", most_recent)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.problems(), ["'mostrecent' should be a positive integer"]);
+    }
+    assert_eq!(api.get("/argo?mostrecent=0").await.status, StatusCode::BAD_REQUEST);
+}
+
+// two later profiles, one sorting before the fixtures by _id and one after, so the latest
+// profiles by timestamp would fall on different pages in _id order
+fn later_profiles() -> api::helpers::MemoryStore {
+    let mut late = common::argo_document(3);
+    late.insert("_id", "5900000_003");
+    let mut latest = common::argo_document(4);
+    latest.insert("_id", "1900000_004");
+    common::store().with_documents("argo", vec![late, latest])
+}
+
+#[actix_web::test]
+async fn argo_most_recent_is_latest_first_by_timestamp() {
+    let api = TestApi::with(later_profiles(), common::config()).await;
+
+    let paged = api.get("/argo?pageSize=2").await;
+    assert_eq!(paged.ids(), ["1900000_004", "4902911_000"]);
+
+    let response = api.get("/argo?mostrecent=3").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.ids(), ["1900000_004", "5900000_003", "4902911_002"]);
+    assert_eq!(response.header("x-next-page-token"), None);
+}
+
+#[actix_web::test]
+async fn argo_most_recent_is_a_single_page() {
+    let api = TestApi::new().await;
+    let token = api.get("/argo?pageSize=1").await.header("x-next-page-token").unwrap();
+
+    for paging in [String::from("pageSize=2"), String::from("page=1"), format!("pageToken={}", token)] {
+        let response = api.get(&format!("/argo?mostrecent=2&{}", paging)).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert!(response.problems()[0].starts_with("'mostrecent' returns a single page of point data"));
+    }
+
+    let too_many = api.get("/argo?mostrecent=10001").await;
+    assert_eq!(too_many.problems(), ["'mostrecent' should be at most 10000"]);
+}
+
+// data constraints ////////////////////////////////////////////////////////////
+
+#[actix_web::test]
+async fn data_constraint_keeps_only_matching_timesteps() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature,salinity,%3E35").await;
+    let document = &response.json()[0];
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(document["data"], json!([[3.0, 4.0], [36.0, 37.0]]));
+    assert_eq!(document["timeseries"], json!(["2020-09-15T12:26:40Z", "2020-09-16T12:26:40Z"]));
+}
+
+#[actix_web::test]
+async fn data_constraints_combine() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?id=a&data=temperature,%3E=2,%3C4").await;
+
+    assert_eq!(response.json()[0]["data"], json!([[2.0, 3.0]]));
+}
+
+#[actix_web::test]
+async fn data_constraint_drops_documents_with_nothing_left() {
+    let api = TestApi::new().await;
+    let response = api.get("/search?data=temperature,%3E100").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn data_constraint_needs_a_variable_and_a_number() {
+    let api = TestApi::new().await;
+
+    let orphan = api.get("/search?data=%3E10").await;
+    assert_eq!(orphan.status, StatusCode::BAD_REQUEST);
+    assert_eq!(orphan.problems(), ["'>10' in 'data' should follow the name of the variable it constrains"]);
+
+    let not_a_number = api.get("/search?data=temperature,%3Ewarm").await;
+    assert_eq!(not_a_number.problems(), ["'>warm' in 'data' should compare to a number, like >10"]);
+}
+
+#[actix_web::test]
+async fn argo_data_constraint_keeps_only_matching_levels() {
+    let api = TestApi::new().await;
+    let response = api.get("/argo?id=4902911_002&data=pressure,temperature,%3E5").await;
+    let profile = &response.json()[0];
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(profile["data"], json!([[5.0, 500.0], [20.0, 6.0]]));
+}
+
+// response size estimates /////////////////////////////////////////////////////
+
+// about 480 bytes per bsose document with both variables over all four timesteps
+fn small_responses() -> api::helpers::Config {
+    let mut config = common::config();
+    config.max_response_bytes = 1000;
+    config
+}
+
+#[actix_web::test]
+async fn search_estimated_too_large_is_refused() {
+    let api = TestApi::with(common::store(), small_responses()).await;
+    let response = api.get("/search?data=all").await;
+
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.json()["code"], "payload_too_large");
+}
+
+#[actix_web::test]
+async fn search_narrowed_under_the_limit_is_served() {
+    let api = TestApi::with(common::store(), small_responses()).await;
+
+    assert_eq!(api.get("/search?data=all&pageSize=1").await.status, StatusCode::OK);
+    assert_eq!(api.get("/search?data=temperature&pageSize=2").await.status, StatusCode::OK);
+    assert_eq!(api.get("/search?data=all&box=[[0,0],[20,20]]").await.status, StatusCode::OK);
+}
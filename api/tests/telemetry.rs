@@ -0,0 +1,44 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use actix_web::test::TestRequest;
+use api::helpers::REQUEST_ID_HEADER;
+use common::TestApi;
+
+#[actix_web::test]
+async fn request_id_is_echoed_when_supplied() {
+    let api = TestApi::new().await;
+    let response = api.call(TestRequest::get().uri("/search?id=a").insert_header((REQUEST_ID_HEADER, "from-the-proxy-1"))).await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header(REQUEST_ID_HEADER).as_deref(), Some("from-the-proxy-1"));
+}
+
+#[actix_web::test]
+async fn request_id_is_generated_when_absent() {
+    let api = TestApi::new().await;
+    let first = api.get("/search?id=a").await.header(REQUEST_ID_HEADER).unwrap();
+    let second = api.get("/search?id=a").await.header(REQUEST_ID_HEADER).unwrap();
+
+    assert!(uuid::Uuid::parse_str(&first).is_ok(), "{}", first);
+    assert_ne!(first, second);
+}
+
+#[actix_web::test]
+async fn request_id_is_replaced_when_unusable() {
+    let api = TestApi::new().await;
+    let long = "x".repeat(200);
+    let response = api.call(TestRequest::get().uri("/search?id=a").insert_header((REQUEST_ID_HEADER, long.as_str()))).await;
+    let id = response.header(REQUEST_ID_HEADER).unwrap();
+
+    assert!(uuid::Uuid::parse_str(&id).is_ok(), "{}", id);
+}
+
+#[actix_web::test]
+async fn request_id_is_set_on_errors() {
+    let api = TestApi::new().await;
+    let response = api.call(TestRequest::get().uri("/search?colour=blue").insert_header((REQUEST_ID_HEADER, "bad-query"))).await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.header(REQUEST_ID_HEADER).as_deref(), Some("bad-query"));
+}
@@ -0,0 +1,137 @@
+mod common;
+
+use actix_web::http::StatusCode;
+use api::helpers::*;
+use async_trait::async_trait;
+use common::TestApi;
+use mongodb::bson::{Bson, Document};
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// counts distinct queries on the way to the fixtures, failing them while `failing` is set
+struct CountingStore {
+    store: MemoryStore,
+    distincts: Arc<AtomicUsize>,
+    failing: Arc<AtomicBool>,
+}
+
+#[async_trait]
+impl DataStore for CountingStore {
+    async fn find(&self, collection: &str, filter: Document, options: QueryOptions) -> Result<DocumentStream, ApiError> {
+        self.store.find(collection, filter, options).await
+    }
+
+    async fn estimated_count(&self, collection: &str) -> Result<u64, ApiError> {
+        self.store.estimated_count(collection).await
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.store.ping().await
+    }
+
+    async fn distinct(&self, collection: &str, field: &str, filter: Document) -> Result<Vec<Bson>, ApiError> {
+        self.distincts.fetch_add(1, Ordering::SeqCst);
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(ApiError::Unavailable(String::from("distinct timed out")));
+        }
+        self.store.distinct(collection, field, filter).await
+    }
+}
+
+#[actix_web::test]
+async fn vocabulary_lists_data_variables() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/bsose/vocabulary?parameter=data").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json(), json!({"parameter": "data", "values": ["temperature", "salinity", "all", "except_data_values"]}));
+}
+
+#[actix_web::test]
+async fn vocabulary_lists_basins_and_levels_in_order() {
+    let api = TestApi::new().await;
+
+    let basins = api.get("/timeseries/bsose/vocabulary?parameter=basin").await;
+    assert_eq!(basins.json()["values"], json!([1.0]));
+
+    let levels = api.get("/timeseries/bsose/vocabulary?parameter=level").await;
+    assert_eq!(levels.json()["values"], json!([5.0, 50.0]));
+}
+
+#[actix_web::test]
+async fn vocabulary_of_a_surface_product_has_no_levels() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/noaasst/vocabulary?parameter=level").await;
+
+    assert_eq!(response.json()["values"], json!([]));
+}
+
+#[actix_web::test]
+async fn vocabulary_lists_compression_and_formats() {
+    let api = TestApi::new().await;
+
+    let compression = api.get("/timeseries/bsose/vocabulary?parameter=compression").await;
+    assert_eq!(compression.json()["values"], json!(["minimal"]));
+
+    let formats = api.get("/timeseries/bsose/vocabulary?parameter=format").await;
+    assert_eq!(formats.json()["values"], json!(params::FORMATS));
+}
+
+#[actix_web::test]
+async fn vocabulary_basins_and_levels_are_read_once_at_loading() {
+    let distincts = Arc::new(AtomicUsize::new(0));
+    let store = CountingStore { store: common::store(), distincts: distincts.clone(), failing: Arc::new(AtomicBool::new(false)) };
+    let api = TestApi::serve(AppState::new(Arc::new(store), common::config())).await;
+    let loaded = distincts.load(Ordering::SeqCst);
+
+    for _ in 0..3 {
+        assert_eq!(api.get("/timeseries/bsose/vocabulary?parameter=basin").await.status, StatusCode::OK);
+        assert_eq!(api.get("/timeseries/bsose/vocabulary?parameter=level").await.status, StatusCode::OK);
+    }
+    assert_eq!(distincts.load(Ordering::SeqCst), loaded);
+}
+
+#[actix_web::test]
+async fn vocabulary_failing_to_load_leaves_searches_working() {
+    let failing = Arc::new(AtomicBool::new(true));
+    let store = CountingStore { store: common::store(), distincts: Arc::new(AtomicUsize::new(0)), failing: failing.clone() };
+    let api = TestApi::serve(AppState::new(Arc::new(store), common::config())).await;
+
+    assert_eq!(api.get("/timeseries/bsose?id=a&data=temperature").await.status, StatusCode::OK);
+    assert_eq!(api.get("/timeseries/bsose/vocabulary?parameter=data").await.status, StatusCode::OK);
+    let basins = api.get("/timeseries/bsose/vocabulary?parameter=basin").await;
+    assert_eq!(basins.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(basins.json()["message"], "Basins and levels of 'bsose' are still loading; try again shortly");
+
+    failing.store(false, Ordering::SeqCst);
+    api.state.load_datasets().await;
+    let levels = api.get("/timeseries/bsose/vocabulary?parameter=level").await;
+    assert_eq!(levels.json()["values"], json!([5.0, 50.0]));
+}
+
+#[actix_web::test]
+async fn vocabulary_needs_one_known_parameter() {
+    let api = TestApi::new().await;
+
+    let missing = api.get("/timeseries/bsose/vocabulary").await;
+    assert_eq!(missing.status, StatusCode::BAD_REQUEST);
+    assert_eq!(missing.problems(), ["'parameter' should be one of data, basin, level, compression, format"]);
+
+    let unknown = api.get("/timeseries/bsose/vocabulary?parameter=colour").await;
+    assert_eq!(unknown.problems(), ["'parameter' should be one of data, basin, level, compression, format"]);
+
+    let twice = api.get("/timeseries/bsose/vocabulary?parameter=data&parameter=basin").await;
+    assert_eq!(twice.problems(), ["'parameter' should only be defined once"]);
+
+    let extra = api.get("/timeseries/bsose/vocabulary?parameter=data&colour=blue").await;
+    assert!(extra.problems()[0].starts_with("'colour' is not a recognized query parameter"));
+}
+
+#[actix_web::test]
+async fn vocabulary_of_an_unknown_dataset_is_not_found() {
+    let api = TestApi::new().await;
+    let response = api.get("/timeseries/nope/vocabulary?parameter=data").await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+}